@@ -0,0 +1,363 @@
+pub mod command;
+pub mod continuous;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod keystroke;
+pub mod layer;
+pub mod pipe;
+#[cfg(test)]
+mod test_switch;
+pub mod volume;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::device::switch::{SwitchInfo, SwitchKind};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Action not found: `{0}`")]
+    NotFound(String),
+    #[error("Unknown key: `{0}`")]
+    UnknownKey(String),
+    #[error("Io error")]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "http")]
+    #[error("Http error")]
+    Http(#[source] Box<ureq::Error>),
+    #[cfg(feature = "websocket")]
+    #[error("WebSocket error")]
+    WebSocket(#[source] Box<tungstenite::Error>),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// スイッチの入力によって実行される処理
+pub trait Action: Send + Sync {
+    /// アクションを実行する
+    ///
+    /// 押下・解放の両方で呼ばれるため、必要に応じて `info.state` を確認してください。
+    fn execute(&self, info: &SwitchInfo) -> Result<()>;
+}
+
+/// アクションIDとアクションの対応表
+#[derive(Default)]
+pub struct ActionMap {
+    actions: HashMap<String, Box<dyn Action>>,
+    /// 設定から登録したキー入力のアクションに使うバックエンド
+    injector: Option<Arc<dyn keystroke::KeyInjector>>,
+}
+
+impl ActionMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 設定から登録したキー入力のアクションに使うバックエンドを指定する
+    ///
+    /// 指定しない場合は、最初にキー入力のアクションを登録した時に [`keystroke::default_injector`] で作成します。
+    pub fn with_injector(mut self, injector: Arc<dyn keystroke::KeyInjector>) -> Self {
+        self.injector = Some(injector);
+        self
+    }
+
+    /// 設定からアクションを登録する
+    ///
+    /// バックエンドが設定されていないキー入力のアクションには、この対応表のバックエンドを設定します。
+    pub fn insert_config(&mut self, id: impl Into<String>, mut config: ActionConfig) {
+        if let ActionKind::Keystroke(action) = &mut config.kind
+            && action.injector.is_none()
+        {
+            let injector = self
+                .injector
+                .get_or_insert_with(keystroke::default_injector);
+            action.injector = Some(injector.clone());
+        }
+
+        self.insert(id, Box::new(config));
+    }
+
+    /// アクションを登録する。同じIDのアクションが既にあれば置き換える
+    pub fn insert(&mut self, id: impl Into<String>, action: Box<dyn Action>) {
+        self.actions.insert(id.into(), action);
+    }
+
+    /// アクションの登録を解除する
+    pub fn remove(&mut self, id: &str) -> Option<Box<dyn Action>> {
+        self.actions.remove(id)
+    }
+
+    /// IDに対応するアクションを実行する
+    pub fn execute(&self, id: &str, info: &SwitchInfo) -> Result<()> {
+        self.actions
+            .get(id)
+            .ok_or_else(|| Error::NotFound(id.to_string()))?
+            .execute(info)
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, ActionConfig)> for ActionMap {
+    fn from_iter<I: IntoIterator<Item = (S, ActionConfig)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        for (id, config) in iter {
+            map.insert_config(id, config);
+        }
+        map
+    }
+}
+
+/// アクションを実行するタイミング
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Trigger {
+    /// スイッチが押されたとき。アナログスイッチでは値が変化するたびに実行する
+    #[default]
+    Press,
+    /// スイッチが離されたとき
+    Release,
+    /// 押下・解放の両方
+    Both,
+}
+
+impl Trigger {
+    fn matches(&self, info: &SwitchInfo) -> bool {
+        match (self, info.kind) {
+            (_, SwitchKind::Analog) | (Self::Both, _) => true,
+            (Self::Press, SwitchKind::Digital) => info.state != 0,
+            (Self::Release, SwitchKind::Digital) => info.state == 0,
+        }
+    }
+}
+
+/// 組み込みアクションの種類
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum ActionKind {
+    /// ローカルのプロセスを起動する
+    Command(command::CommandAction),
+    /// 名前付きパイプに書き込む
+    Pipe(pipe::PipeAction),
+    /// Unixドメインソケットに書き込む
+    #[cfg(unix)]
+    UnixSocket(pipe::UnixSocketAction),
+    /// HTTPリクエストを送信する
+    #[cfg(feature = "http")]
+    Http(http::HttpAction),
+    /// WebSocketでメッセージを送信する
+    #[cfg(feature = "websocket")]
+    WebSocket(websocket::WebSocketAction),
+    /// キー入力を送信する
+    Keystroke(keystroke::KeystrokeAction),
+}
+
+/// 設定ファイルに保存できる組み込みアクションの設定
+///
+/// # Example
+///
+/// ```
+/// use ardeck::action::ActionConfig;
+///
+/// let config: ActionConfig = serde_json::from_str(r#"{
+///     "type": "command",
+///     "program": "notify-send",
+///     "args": ["pin {pin}", "state {state}"]
+/// }"#).unwrap();
+/// ```
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionConfig {
+    /// アクションを実行するタイミング
+    #[serde(default)]
+    pub trigger: Trigger,
+    /// アクションの種類と設定
+    #[serde(flatten)]
+    pub kind: ActionKind,
+}
+
+impl Action for ActionConfig {
+    fn execute(&self, info: &SwitchInfo) -> Result<()> {
+        if !self.trigger.matches(info) {
+            return Ok(());
+        }
+
+        match &self.kind {
+            ActionKind::Command(action) => action.execute(info),
+            ActionKind::Pipe(action) => action.execute(info),
+            #[cfg(unix)]
+            ActionKind::UnixSocket(action) => action.execute(info),
+            #[cfg(feature = "http")]
+            ActionKind::Http(action) => action.execute(info),
+            #[cfg(feature = "websocket")]
+            ActionKind::WebSocket(action) => action.execute(info),
+            ActionKind::Keystroke(action) => action.execute(info),
+        }
+    }
+}
+
+/// テンプレート文字列中の `{pin}`, `{state}`, `{kind}` をスイッチの情報で置き換える
+pub fn render(template: &str, info: &SwitchInfo) -> String {
+    let kind = match info.kind {
+        SwitchKind::Digital => "digital",
+        SwitchKind::Analog => "analog",
+    };
+
+    template
+        .replace("{pin}", &info.pin.to_string())
+        .replace("{state}", &info.state.to_string())
+        .replace("{kind}", kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{test_switch::switch, *};
+
+    /// JSONから読み込み、書き出した結果が元のJSONと一致することを確かめる
+    fn round_trip(value: serde_json::Value) -> ActionConfig {
+        let config: ActionConfig = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&config).unwrap(), value);
+        config
+    }
+
+    #[test]
+    fn serde_command() {
+        let config = round_trip(json!({
+            "trigger": "release",
+            "type": "command",
+            "program": "notify-send",
+            "args": ["pin {pin}"],
+            "env": { "STATE": "{state}" },
+            "currentDir": "/tmp",
+        }));
+        assert_eq!(config.trigger, Trigger::Release);
+        assert!(matches!(
+            config.kind,
+            ActionKind::Command(command::CommandAction { ref program, .. }) if program == "notify-send"
+        ));
+
+        // 省略した項目は既定値になる
+        let config: ActionConfig =
+            serde_json::from_value(json!({ "type": "command", "program": "true" })).unwrap();
+        assert_eq!(config.trigger, Trigger::Press);
+        assert_eq!(
+            config.kind,
+            ActionKind::Command(command::CommandAction {
+                program: "true".into(),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn serde_pipe() {
+        let config = round_trip(json!({
+            "trigger": "press",
+            "type": "pipe",
+            "path": "/tmp/ardeck.fifo",
+            "message": "{pin}:{state}",
+        }));
+        assert!(matches!(config.kind, ActionKind::Pipe(_)));
+
+        #[cfg(unix)]
+        {
+            let config = round_trip(json!({
+                "trigger": "both",
+                "type": "unixSocket",
+                "path": "/tmp/ardeck.sock",
+                "message": "{kind}",
+            }));
+            assert!(matches!(config.kind, ActionKind::UnixSocket(_)));
+        }
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn serde_http() {
+        let config = round_trip(json!({
+            "trigger": "press",
+            "type": "http",
+            "url": "http://localhost:8080/pin/{pin}",
+            "method": "PUT",
+            "headers": { "Content-Type": "text/plain" },
+            "body": "{state}",
+        }));
+        assert!(matches!(config.kind, ActionKind::Http(_)));
+
+        let config: ActionConfig =
+            serde_json::from_value(json!({ "type": "http", "url": "http://localhost" })).unwrap();
+        let ActionKind::Http(http) = config.kind else {
+            panic!("not http: {:?}", config.kind);
+        };
+        assert_eq!(http.method, "POST");
+        assert_eq!(http.body, None);
+    }
+
+    #[cfg(feature = "websocket")]
+    #[test]
+    fn serde_websocket() {
+        let config = round_trip(json!({
+            "trigger": "press",
+            "type": "webSocket",
+            "url": "ws://localhost:4455",
+            "message": "{pin}",
+        }));
+        assert!(matches!(config.kind, ActionKind::WebSocket(_)));
+    }
+
+    #[test]
+    fn serde_keystroke() {
+        let config = round_trip(json!({
+            "trigger": "press",
+            "type": "keystroke",
+            "keys": ["KEY_LEFTCTRL", "KEY_C"],
+        }));
+        assert_eq!(
+            config.kind,
+            ActionKind::Keystroke(keystroke::KeystrokeAction {
+                keys: vec!["KEY_LEFTCTRL".into(), "KEY_C".into()],
+                ..Default::default()
+            })
+        );
+
+        assert!(serde_json::from_value::<ActionConfig>(json!({ "type": "unknown" })).is_err());
+    }
+
+    #[test]
+    fn trigger() {
+        let press = switch(SwitchKind::Digital, 0, 1);
+        let release = switch(SwitchKind::Digital, 0, 0);
+        let analog = switch(SwitchKind::Analog, 0, 0);
+
+        assert!(Trigger::Press.matches(&press));
+        assert!(!Trigger::Press.matches(&release));
+        assert!(!Trigger::Release.matches(&press));
+        assert!(Trigger::Release.matches(&release));
+        assert!(Trigger::Both.matches(&press));
+        assert!(Trigger::Both.matches(&release));
+
+        // アナログスイッチは常に実行する
+        assert!(Trigger::Press.matches(&analog));
+        assert!(Trigger::Release.matches(&analog));
+        assert!(Trigger::Both.matches(&analog));
+    }
+
+    #[test]
+    fn render_placeholders() {
+        assert_eq!(
+            render(
+                "pin={pin} state={state} kind={kind}",
+                &switch(SwitchKind::Analog, 3, 512)
+            ),
+            "pin=3 state=512 kind=analog"
+        );
+        assert_eq!(
+            render("{pin}{pin} {unknown}", &switch(SwitchKind::Digital, 1, 1)),
+            "11 {unknown}"
+        );
+        assert_eq!(render("", &switch(SwitchKind::Digital, 1, 1)), "");
+    }
+}
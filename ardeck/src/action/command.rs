@@ -0,0 +1,56 @@
+use std::{collections::BTreeMap, path::PathBuf, process::Stdio, thread};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::{Action, Result, render},
+    device::switch::SwitchInfo,
+};
+
+/// ローカルのプロセスを起動するアクション
+///
+/// 引数と環境変数の値には [`render`] のテンプレートが使えます。
+/// プロセスの終了は待たずに戻ります。
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandAction {
+    /// 実行するプログラム
+    pub program: String,
+    /// コマンドライン引数
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// 追加する環境変数
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// 作業ディレクトリ
+    #[serde(default)]
+    pub current_dir: Option<PathBuf>,
+}
+
+impl Action for CommandAction {
+    fn execute(&self, info: &SwitchInfo) -> Result<()> {
+        let mut command = std::process::Command::new(&self.program);
+        command
+            .args(self.args.iter().map(|arg| render(arg, info)))
+            .envs(self.env.iter().map(|(k, v)| (k, render(v, info))))
+            .stdin(Stdio::null());
+
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+
+        let mut child = command.spawn()?;
+        let program = self.program.clone();
+
+        // ゾンビプロセスを残さないように終了を待つ
+        thread::spawn(move || match child.wait() {
+            Ok(status) if !status.success() => {
+                log::warn!("`{}` exited with {}", program, status)
+            }
+            Ok(_) => {}
+            Err(e) => log::error!("Failed wait `{}`: {}", program, e),
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    action::{Action, Result},
+    device::switch::{SwitchInfo, SwitchKind},
+};
+
+/// アナログスイッチの最大値(10bit)
+const ANALOG_MAX: u16 = 0x3FF;
+
+/// 連続値を受け取るアクション ex: 音量, 明るさ, シーク位置
+pub trait ContinuousAction: Send + Sync {
+    /// 正規化された値(`0.0..=1.0`)で更新する
+    fn update(&self, value: f32) -> Result<()>;
+}
+
+/// スイッチの状態を `0.0..=1.0` に正規化する
+///
+/// デジタルスイッチは `0.0` か `1.0` になります。
+pub fn normalize(info: &SwitchInfo) -> f32 {
+    match info.kind {
+        SwitchKind::Digital => (info.state != 0) as u8 as f32,
+        SwitchKind::Analog => info.state.min(ANALOG_MAX) as f32 / ANALOG_MAX as f32,
+    }
+}
+
+/// 連続値アクションの更新頻度を制限して [`Action`] として扱えるようにする
+///
+/// 前回の更新から `interval` が経過していない値と、変化量が `min_delta` 未満の値はすぐには反映されません。
+/// ただし端の値(`0.0`, `1.0`)は常にすぐ反映されます。
+///
+/// 反映されなかった値のうち最後のものは、前回の更新から `interval` が経過した時点で反映されます。
+/// ノブを回し終えた位置が途中の値のまま残ることはありません。
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use ardeck::action::{ActionMap, continuous::RateLimited, volume::{MockVolumeBackend, VolumeAction}};
+///
+/// let mut actions = ActionMap::new();
+/// let volume = VolumeAction::new(MockVolumeBackend::default());
+/// actions.insert(
+///     "volume",
+///     Box::new(RateLimited::new(volume, Duration::from_millis(50), 0.01)),
+/// );
+/// ```
+pub struct RateLimited<A> {
+    inner: Arc<Inner<A>>,
+}
+
+struct Inner<A> {
+    action: A,
+    interval: Duration,
+    min_delta: f32,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// 最後に反映した時刻と値
+    last: Option<(Instant, f32)>,
+    /// まだ反映していない最後の値
+    pending: Option<f32>,
+    /// 保留中の値を反映するスレッドが動いているか
+    flushing: bool,
+}
+
+impl<A: ContinuousAction + 'static> RateLimited<A> {
+    pub fn new(action: A, interval: Duration, min_delta: f32) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                action,
+                interval,
+                min_delta,
+                state: Mutex::new(State::default()),
+            }),
+        }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner.action
+    }
+}
+
+impl<A: ContinuousAction> Inner<A> {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 前回の更新から `interval` が経過するまで待ち、保留中の値を反映する
+    ///
+    /// [`RateLimited::execute`](Action::execute) と同じくロックを保持したまま反映するので、
+    /// 後から届いた値が古い保留中の値で上書きされることはありません。
+    fn flush(&self) {
+        loop {
+            let deadline = {
+                let mut state = self.state();
+                let Some((at, prev)) = state.last else {
+                    state.flushing = false;
+                    return;
+                };
+                let deadline = at + self.interval;
+
+                if Instant::now() >= deadline {
+                    state.flushing = false;
+                    let Some(value) = state.pending.take().filter(|value| *value != prev) else {
+                        return;
+                    };
+
+                    let now = Instant::now();
+                    match self.action.update(value) {
+                        Ok(()) => state.last = Some((now, value)),
+                        Err(e) => log::warn!("Failed update pending value {}: {}", value, e),
+                    }
+                    return;
+                }
+
+                deadline
+            };
+
+            thread::sleep(deadline - Instant::now());
+        }
+    }
+}
+
+impl<A: ContinuousAction + 'static> Action for RateLimited<A> {
+    fn execute(&self, info: &SwitchInfo) -> Result<()> {
+        let value = normalize(info);
+        let now = Instant::now();
+        let mut state = self.inner.state();
+
+        if let Some((at, prev)) = state.last {
+            let edge = value == 0.0 || value == 1.0;
+            let delta = (value - prev).abs();
+
+            if delta == 0.0 {
+                state.pending = None;
+                return Ok(());
+            }
+            if !edge && (delta < self.inner.min_delta || now - at < self.inner.interval) {
+                state.pending = Some(value);
+
+                if !state.flushing {
+                    state.flushing = true;
+                    let inner = self.inner.clone();
+                    thread::spawn(move || inner.flush());
+                }
+                return Ok(());
+            }
+        }
+
+        self.inner.action.update(value)?;
+        state.last = Some((now, value));
+        state.pending = None;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::{
+        test_switch::analog,
+        volume::{MockVolumeBackend, VolumeAction},
+    };
+
+    /// 最後に反映された値が `expected` になるまで待つ
+    fn wait_last(action: &RateLimited<VolumeAction<MockVolumeBackend>>, expected: u16) -> Vec<f32> {
+        let expected = expected as f32 / ANALOG_MAX as f32;
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        loop {
+            let history = action.inner().backend().history();
+            if history.last() == Some(&expected) || Instant::now() > deadline {
+                return history;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /// 更新に時間がかかり、同時に更新されたことを記録するアクション
+    #[derive(Default)]
+    struct SlowAction {
+        updating: std::sync::atomic::AtomicBool,
+        overlapped: std::sync::atomic::AtomicBool,
+        history: Mutex<Vec<f32>>,
+    }
+
+    impl ContinuousAction for SlowAction {
+        fn update(&self, value: f32) -> Result<()> {
+            use std::sync::atomic::Ordering;
+
+            if self.updating.swap(true, Ordering::SeqCst) {
+                self.overlapped.store(true, Ordering::SeqCst);
+            }
+            thread::sleep(Duration::from_millis(50));
+            self.history.lock().unwrap().push(value);
+            self.updating.store(false, Ordering::SeqCst);
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flush_does_not_overtake() {
+        let action = RateLimited::new(SlowAction::default(), Duration::from_millis(100), 0.01);
+
+        action.execute(&analog(100)).unwrap();
+        action.execute(&analog(200)).unwrap();
+
+        // 保留中の値を反映している途中に端の値が届く
+        thread::sleep(Duration::from_millis(70));
+        action.execute(&analog(ANALOG_MAX)).unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        let inner = action.inner();
+        assert!(!inner.overlapped.load(std::sync::atomic::Ordering::SeqCst));
+        assert_eq!(inner.history.lock().unwrap().last(), Some(&1.0));
+    }
+
+    #[test]
+    fn last_value_arrives() {
+        let action = RateLimited::new(
+            VolumeAction::new(MockVolumeBackend::default()),
+            Duration::from_millis(200),
+            0.05,
+        );
+
+        // 間隔内に回し続けて止めた位置が反映される
+        for state in [100, 200, 300, 400] {
+            action.execute(&analog(state)).unwrap();
+        }
+        let history = wait_last(&action, 400);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1], 400.0 / ANALOG_MAX as f32);
+
+        // 変化量が小さい値も最後には反映される
+        action.execute(&analog(410)).unwrap();
+        let history = wait_last(&action, 410);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[2], 410.0 / ANALOG_MAX as f32);
+
+        // 元の値に戻った場合は送らない
+        action.execute(&analog(420)).unwrap();
+        action.execute(&analog(410)).unwrap();
+        thread::sleep(Duration::from_millis(400));
+        assert_eq!(action.inner().backend().history().len(), 3);
+    }
+}
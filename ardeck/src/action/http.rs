@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::{Action, Error, Result, render},
+    device::switch::SwitchInfo,
+};
+
+fn default_method() -> String {
+    "POST".into()
+}
+
+/// HTTPリクエストを送信するアクション
+///
+/// ローカルのエンドポイント向けのため、TLSには対応していません。
+/// URLとボディには [`render`] のテンプレートが使えます。
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpAction {
+    /// 送信先のURL
+    pub url: String,
+    /// HTTPメソッド
+    #[serde(default = "default_method")]
+    pub method: String,
+    /// 追加するヘッダー
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// リクエストボディ
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+impl Action for HttpAction {
+    fn execute(&self, info: &SwitchInfo) -> Result<()> {
+        let mut request = ureq::http::Request::builder()
+            .method(self.method.as_str())
+            .uri(render(&self.url, info));
+
+        for (key, value) in self.headers.iter() {
+            request = request.header(key, value);
+        }
+
+        let body = self
+            .body
+            .as_ref()
+            .map(|body| render(body, info))
+            .unwrap_or_default();
+
+        let request = request
+            .body(body)
+            .map_err(|e| Error::Http(Box::new(e.into())))?;
+
+        let response = ureq::run(request).map_err(|e| Error::Http(Box::new(e)))?;
+        log::debug!("{} {} -> {}", self.method, self.url, response.status());
+
+        Ok(())
+    }
+}
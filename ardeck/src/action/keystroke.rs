@@ -0,0 +1,272 @@
+use std::{fmt, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::{Action, Result},
+    device::switch::SwitchInfo,
+};
+
+/// キー入力をOSへ送信するバックエンド
+///
+/// キー名はLinuxの `input-event-codes.h` に合わせます。 ex: `KEY_LEFTCTRL`, `KEY_C`
+pub trait KeyInjector: Send + Sync {
+    /// キーを押す
+    fn press(&self, key: &str) -> Result<()>;
+    /// キーを離す
+    fn release(&self, key: &str) -> Result<()>;
+}
+
+/// キー入力をログに出力するだけのバックエンド
+///
+/// 利用できるバックエンドがない環境で使われます。
+#[derive(Debug, Default)]
+pub struct LogInjector;
+
+impl KeyInjector for LogInjector {
+    fn press(&self, key: &str) -> Result<()> {
+        log::info!("Key press: {}", key);
+        Ok(())
+    }
+
+    fn release(&self, key: &str) -> Result<()> {
+        log::info!("Key release: {}", key);
+        Ok(())
+    }
+}
+
+/// uinputの仮想キーボードからキー入力を送信するバックエンド
+///
+/// `/dev/uinput` への書き込み権限が必要です。
+#[cfg(all(target_os = "linux", feature = "uinput"))]
+pub struct UinputInjector {
+    device: std::sync::Mutex<evdev::uinput::VirtualDevice>,
+}
+
+#[cfg(all(target_os = "linux", feature = "uinput"))]
+impl UinputInjector {
+    pub fn new() -> Result<Self> {
+        // KEY_ESC から KEY_MICMUTE までの一般的なキーを登録する
+        let keys: evdev::AttributeSet<evdev::KeyCode> = (1..=248).map(evdev::KeyCode).collect();
+
+        let device = evdev::uinput::VirtualDevice::builder()?
+            .name("ardeck virtual keyboard")
+            .with_keys(&keys)?
+            .build()?;
+
+        Ok(Self {
+            device: std::sync::Mutex::new(device),
+        })
+    }
+
+    fn emit(&self, key: &str, value: i32) -> Result<()> {
+        let code: evdev::KeyCode = key
+            .parse()
+            .map_err(|_| crate::action::Error::UnknownKey(key.to_string()))?;
+
+        self.device
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .emit(&[*evdev::KeyEvent::new(code, value)])?;
+
+        Ok(())
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "uinput"))]
+impl KeyInjector for UinputInjector {
+    fn press(&self, key: &str) -> Result<()> {
+        self.emit(key, 1)
+    }
+
+    fn release(&self, key: &str) -> Result<()> {
+        self.emit(key, 0)
+    }
+}
+
+/// 環境で利用できるキー入力のバックエンドを作成する
+///
+/// `uinput` 機能が有効で仮想キーボードを作成できればそれを、できなければ [`LogInjector`] を返します。
+pub fn default_injector() -> Arc<dyn KeyInjector> {
+    #[cfg(all(target_os = "linux", feature = "uinput"))]
+    match UinputInjector::new() {
+        Ok(injector) => return Arc::new(injector),
+        Err(e) => log::warn!("Failed create uinput device: {}", e),
+    }
+
+    Arc::new(LogInjector)
+}
+
+/// キーの組み合わせを押して離すアクション
+///
+/// `keys` の順に押し、逆順に離します。
+/// 途中のキーを押せなかった場合も、それまでに押したキーは必ず離してから最初のエラーを返します。
+///
+/// キー入力は [`KeystrokeAction::injector`] に送られます。設定されていなければログに出力するだけです。
+/// [`ActionMap`](crate::action::ActionMap) に設定から登録すると、`ActionMap` のバックエンドが設定されます。
+#[derive(Clone, Deserialize, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct KeystrokeAction {
+    /// 押すキーの一覧 ex: `["KEY_LEFTCTRL", "KEY_C"]`
+    pub keys: Vec<String>,
+    /// キー入力を送信するバックエンド。設定ファイルには保存されません
+    #[serde(skip)]
+    pub injector: Option<Arc<dyn KeyInjector>>,
+}
+
+impl KeystrokeAction {
+    /// キー入力を送信するバックエンドを設定する
+    pub fn with_injector(mut self, injector: Arc<dyn KeyInjector>) -> Self {
+        self.injector = Some(injector);
+        self
+    }
+}
+
+impl fmt::Debug for KeystrokeAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeystrokeAction")
+            .field("keys", &self.keys)
+            .field("injector", &self.injector.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+impl PartialEq for KeystrokeAction {
+    fn eq(&self, other: &Self) -> bool {
+        let injector = match (&self.injector, &other.injector) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        self.keys == other.keys && injector
+    }
+}
+
+impl Action for KeystrokeAction {
+    fn execute(&self, _info: &SwitchInfo) -> Result<()> {
+        let injector = self
+            .injector
+            .as_deref()
+            .unwrap_or(&LogInjector as &dyn KeyInjector);
+
+        let mut result = Ok(());
+        let mut pressed = 0;
+        for key in self.keys.iter() {
+            if let Err(e) = injector.press(key) {
+                result = Err(e);
+                break;
+            }
+            pressed += 1;
+        }
+
+        // 押したキーが押されたままにならないよう、エラーがあっても全て離す
+        for key in self.keys[..pressed].iter().rev() {
+            if let Err(e) = injector.release(key) {
+                if result.is_ok() {
+                    result = Err(e);
+                } else {
+                    log::warn!("Failed release {}: {}", key, e);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::action::Error;
+
+    /// 送られたキー入力を記録し、`fail` に含まれる入力 ex: `"press KEY_C"` では失敗するバックエンド
+    #[derive(Default)]
+    struct MockInjector {
+        events: Mutex<Vec<String>>,
+        fail: Vec<&'static str>,
+    }
+
+    impl MockInjector {
+        fn record(&self, event: &str, key: &str) -> Result<()> {
+            let event = format!("{} {}", event, key);
+            let fail = self.fail.contains(&event.as_str());
+            self.events.lock().unwrap().push(event);
+            if fail {
+                return Err(Error::UnknownKey(key.to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    impl KeyInjector for MockInjector {
+        fn press(&self, key: &str) -> Result<()> {
+            self.record("press", key)
+        }
+
+        fn release(&self, key: &str) -> Result<()> {
+            self.record("release", key)
+        }
+    }
+
+    fn execute(keys: &[&str], fail: Vec<&'static str>) -> (Result<()>, Vec<String>) {
+        let injector = Arc::new(MockInjector {
+            fail,
+            ..Default::default()
+        });
+        let action = KeystrokeAction {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            ..Default::default()
+        }
+        .with_injector(injector.clone());
+
+        let result = action.execute(&SwitchInfo::default());
+        (result, injector.events.lock().unwrap().clone())
+    }
+
+    #[test]
+    fn press_and_release() {
+        let (result, events) = execute(&["KEY_LEFTCTRL", "KEY_C"], vec![]);
+        assert!(result.is_ok());
+        assert_eq!(
+            events,
+            [
+                "press KEY_LEFTCTRL",
+                "press KEY_C",
+                "release KEY_C",
+                "release KEY_LEFTCTRL"
+            ]
+        );
+    }
+
+    #[test]
+    fn release_after_failure() {
+        // 押せなかったキー以降は押さず、押したキーは離す
+        let keys = ["KEY_LEFTCTRL", "KEY_LEFTSHIFT", "KEY_UNKNOWN", "KEY_C"];
+        let (result, events) = execute(&keys, vec!["press KEY_UNKNOWN"]);
+        assert!(matches!(result, Err(Error::UnknownKey(key)) if key == "KEY_UNKNOWN"));
+        assert_eq!(
+            events,
+            [
+                "press KEY_LEFTCTRL",
+                "press KEY_LEFTSHIFT",
+                "press KEY_UNKNOWN",
+                "release KEY_LEFTSHIFT",
+                "release KEY_LEFTCTRL"
+            ]
+        );
+
+        // 離せないキーがあっても残りのキーは離し、最初のエラーを返す
+        let keys = ["KEY_LEFTCTRL", "KEY_LEFTSHIFT", "KEY_C"];
+        let (result, events) = execute(&keys, vec!["release KEY_C", "release KEY_LEFTSHIFT"]);
+        assert!(matches!(result, Err(Error::UnknownKey(key)) if key == "KEY_C"));
+        assert_eq!(
+            events[3..],
+            [
+                "release KEY_C",
+                "release KEY_LEFTSHIFT",
+                "release KEY_LEFTCTRL"
+            ]
+        );
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::ActionConfig,
+    device::switch::{SwitchInfo, SwitchKind},
+};
+
+/// ピンに割り当てる動作
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum Binding {
+    /// アクションを実行する
+    Action { id: String },
+    /// 指定したページへ切り替える
+    SwitchLayer { page: String },
+    /// 押している間だけ指定したページへ切り替える
+    MomentaryLayer { page: String },
+    /// 次のページへ切り替える
+    NextPage,
+    /// 前のページへ切り替える
+    PrevPage,
+}
+
+/// ピンとその動作の対応をまとめたページ
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Page {
+    /// ページ名
+    pub name: String,
+    /// ピン番号と動作の対応
+    pub bindings: BTreeMap<u8, Binding>,
+}
+
+impl Page {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            bindings: BTreeMap::new(),
+        }
+    }
+
+    pub fn bind(mut self, pin: u8, binding: Binding) -> Self {
+        self.bindings.insert(pin, binding);
+        self
+    }
+}
+
+/// 複数のページを持つアクションのプロファイル
+///
+/// 先頭のページが初期状態でアクティブになります。
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    /// プロファイル名
+    pub name: String,
+    /// ページ一覧
+    pub pages: Vec<Page>,
+    /// [`Binding::Action`] から参照されるアクションの設定
+    #[serde(default)]
+    pub actions: BTreeMap<String, ActionConfig>,
+}
+
+impl Profile {
+    fn page_index(&self, name: &str) -> Option<usize> {
+        self.pages.iter().position(|page| page.name == name)
+    }
+}
+
+/// レイヤーの状態が変化したときに発行されるイベント
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayerEvent {
+    /// アクティブなページが切り替わった
+    Changed {
+        /// 対象のデバイスID
+        device_id: String,
+        /// 切り替え前のページ名
+        from: String,
+        /// 切り替え後のページ名
+        to: String,
+    },
+}
+
+type LayerHandler = Box<dyn Fn(LayerEvent) + Send + Sync + 'static>;
+
+/// デバイス1台分のレイヤーの状態
+#[derive(Debug, Clone, Default)]
+struct LayerState {
+    /// 切り替え済みのページ
+    base: usize,
+    /// 押している間だけ有効なページ。後から押されたものが優先される
+    momentary: Vec<(u8, usize)>,
+    /// 押下中のピンと、押下時に解決された動作
+    held: HashMap<u8, Binding>,
+}
+
+impl LayerState {
+    fn active(&self) -> usize {
+        self.momentary
+            .last()
+            .map(|(_, page)| *page)
+            .unwrap_or(self.base)
+    }
+
+    /// プロファイルのページが減った場合に、存在しないページを指す状態を取り除く
+    ///
+    /// 切り替え済みのページが存在しなければ先頭のページに戻します。
+    fn clamp(&mut self, len: usize) {
+        if self.base >= len {
+            self.base = 0;
+        }
+        self.momentary.retain(|(_, page)| *page < len);
+    }
+}
+
+/// デバイスごとにアクティブなレイヤーを追跡する
+///
+/// # Example
+///
+/// ```
+/// use ardeck::action::layer::{Binding, LayerTracker, Page, Profile};
+/// use ardeck::device::switch::SwitchInfo;
+///
+/// let profile = Profile {
+///     name: "default".into(),
+///     pages: vec![
+///         Page::new("main").bind(0, Binding::NextPage),
+///         Page::new("media").bind(0, Binding::NextPage),
+///     ],
+///     ..Default::default()
+/// };
+///
+/// let mut tracker = LayerTracker::new();
+/// let press = SwitchInfo { pin: 0, state: 1, ..Default::default() };
+/// tracker.process("2341-0043", &profile, &press);
+/// assert_eq!(tracker.active_page("2341-0043", &profile), Some("media"));
+/// ```
+#[derive(Default)]
+pub struct LayerTracker {
+    states: HashMap<String, LayerState>,
+    handler: Vec<LayerHandler>,
+}
+
+impl LayerTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// レイヤーが切り替わったときに実行するハンドラー
+    pub fn add_handler(&mut self, handler: LayerHandler) {
+        self.handler.push(handler);
+    }
+
+    /// デバイスで現在アクティブなページ名を取得する
+    ///
+    /// アクティブなページがプロファイルに存在しない場合は先頭のページを返します。
+    pub fn active_page<'a>(&self, device_id: &str, profile: &'a Profile) -> Option<&'a str> {
+        let index = self
+            .states
+            .get(device_id)
+            .map(LayerState::active)
+            .unwrap_or(0);
+
+        profile
+            .pages
+            .get(index)
+            .or(profile.pages.first())
+            .map(|page| page.name.as_str())
+    }
+
+    /// デバイスのレイヤーの状態を初期化する
+    pub fn reset(&mut self, device_id: &str) {
+        self.states.remove(device_id);
+    }
+
+    /// スイッチの入力を処理する
+    ///
+    /// レイヤー操作はこの中で処理され、実行すべきアクションがあればそのIDを返します。
+    pub fn process(
+        &mut self,
+        device_id: &str,
+        profile: &Profile,
+        info: &SwitchInfo,
+    ) -> Option<String> {
+        if profile.pages.is_empty() {
+            return None;
+        }
+
+        let state = self.states.entry(device_id.to_string()).or_default();
+        state.clamp(profile.pages.len());
+        let before = state.active();
+
+        let action = match info.kind {
+            // アナログスイッチは押下・解放を持たないので、アクションのみ扱う
+            SwitchKind::Analog => match profile.pages.get(before)?.bindings.get(&info.pin)? {
+                Binding::Action { id } => Some(id.clone()),
+                _ => None,
+            },
+            SwitchKind::Digital if info.state != 0 => {
+                let binding = profile.pages.get(before)?.bindings.get(&info.pin)?.clone();
+                state.held.insert(info.pin, binding.clone());
+
+                match binding {
+                    Binding::Action { id } => Some(id),
+                    Binding::SwitchLayer { page } => {
+                        if let Some(index) = profile.page_index(&page) {
+                            state.base = index;
+                        } else {
+                            log::warn!("Page not found: {}", page);
+                        }
+                        None
+                    }
+                    Binding::MomentaryLayer { page } => {
+                        if let Some(index) = profile.page_index(&page) {
+                            state.momentary.push((info.pin, index));
+                        } else {
+                            log::warn!("Page not found: {}", page);
+                        }
+                        None
+                    }
+                    Binding::NextPage => {
+                        state.base = (state.base + 1) % profile.pages.len();
+                        None
+                    }
+                    Binding::PrevPage => {
+                        state.base = (state.base + profile.pages.len() - 1) % profile.pages.len();
+                        None
+                    }
+                }
+            }
+            SwitchKind::Digital => {
+                // 解放は押下時に解決した動作へ送る
+                match state.held.remove(&info.pin)? {
+                    Binding::Action { id } => Some(id),
+                    Binding::MomentaryLayer { .. } => {
+                        state.momentary.retain(|(pin, _)| *pin != info.pin);
+                        None
+                    }
+                    _ => None,
+                }
+            }
+        };
+
+        let after = state.active();
+        if before != after
+            && let (Some(from), Some(to)) = (profile.pages.get(before), profile.pages.get(after))
+        {
+            let event = LayerEvent::Changed {
+                device_id: device_id.to_string(),
+                from: from.name.clone(),
+                to: to.name.clone(),
+            };
+            log::debug!("{:?}", event);
+
+            for handler in self.handler.iter() {
+                handler(event.clone());
+            }
+        }
+
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn switch(pin: u8, state: u16) -> SwitchInfo {
+        SwitchInfo {
+            pin,
+            state,
+            ..Default::default()
+        }
+    }
+
+    fn profile() -> Profile {
+        Profile {
+            name: "test".into(),
+            pages: vec![
+                Page::new("main")
+                    .bind(0, Binding::Action { id: "a".into() })
+                    .bind(1, Binding::MomentaryLayer { page: "fn".into() })
+                    .bind(2, Binding::NextPage),
+                Page::new("fn")
+                    .bind(0, Binding::Action { id: "b".into() })
+                    .bind(2, Binding::PrevPage),
+                Page::new("media").bind(
+                    2,
+                    Binding::SwitchLayer {
+                        page: "main".into(),
+                    },
+                ),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn momentary() {
+        let profile = profile();
+        let mut tracker = LayerTracker::new();
+
+        assert_eq!(
+            tracker.process("dev", &profile, &switch(0, 1)),
+            Some("a".into())
+        );
+        assert_eq!(tracker.process("dev", &profile, &switch(1, 1)), None);
+        assert_eq!(tracker.active_page("dev", &profile), Some("fn"));
+
+        // 押下時のページで解放される
+        assert_eq!(
+            tracker.process("dev", &profile, &switch(0, 0)),
+            Some("a".into())
+        );
+        assert_eq!(
+            tracker.process("dev", &profile, &switch(0, 1)),
+            Some("b".into())
+        );
+
+        assert_eq!(tracker.process("dev", &profile, &switch(1, 0)), None);
+        assert_eq!(tracker.active_page("dev", &profile), Some("main"));
+        assert_eq!(
+            tracker.process("dev", &profile, &switch(0, 0)),
+            Some("b".into())
+        );
+    }
+
+    #[test]
+    fn switch_pages() {
+        let profile = profile();
+        let changes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut tracker = LayerTracker::new();
+        let c = changes.clone();
+        tracker.add_handler(Box::new(move |event| c.lock().unwrap().push(event)));
+
+        tracker.process("dev", &profile, &switch(2, 1));
+        tracker.process("dev", &profile, &switch(2, 0));
+        assert_eq!(tracker.active_page("dev", &profile), Some("fn"));
+        assert_eq!(tracker.active_page("other", &profile), Some("main"));
+
+        tracker.process("dev", &profile, &switch(2, 1));
+        assert_eq!(tracker.active_page("dev", &profile), Some("main"));
+
+        assert_eq!(
+            changes.lock().unwrap()[0],
+            LayerEvent::Changed {
+                device_id: "dev".into(),
+                from: "main".into(),
+                to: "fn".into(),
+            }
+        );
+        assert_eq!(changes.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn profile_shrinks() {
+        let mut profile = profile();
+        let mut tracker = LayerTracker::new();
+
+        tracker.process("dev", &profile, &switch(2, 1));
+        tracker.process("dev", &profile, &switch(2, 0));
+        assert_eq!(tracker.active_page("dev", &profile), Some("fn"));
+
+        profile.pages.truncate(1);
+        assert_eq!(tracker.active_page("dev", &profile), Some("main"));
+        assert_eq!(
+            tracker.process("dev", &profile, &switch(0, 1)),
+            Some("a".into())
+        );
+
+        // 押したままのページが消えても、解放で取り除かれる
+        let mut profile = self::profile();
+        tracker.reset("dev");
+        tracker.process("dev", &profile, &switch(1, 1));
+        assert_eq!(tracker.active_page("dev", &profile), Some("fn"));
+        profile.pages.truncate(1);
+        assert_eq!(tracker.process("dev", &profile, &switch(1, 0)), None);
+        assert_eq!(tracker.active_page("dev", &profile), Some("main"));
+    }
+}
@@ -0,0 +1,50 @@
+use std::{fs::OpenOptions, io::Write, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::{Action, Result, render},
+    device::switch::SwitchInfo,
+};
+
+/// 名前付きパイプ(FIFO)にメッセージを書き込むアクション
+///
+/// Windowsでは `\\.\pipe\name` 形式のパスを指定します。
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PipeAction {
+    /// パイプのパス
+    pub path: PathBuf,
+    /// 書き込むメッセージ
+    pub message: String,
+}
+
+impl Action for PipeAction {
+    fn execute(&self, info: &SwitchInfo) -> Result<()> {
+        let mut pipe = OpenOptions::new().write(true).open(&self.path)?;
+        pipe.write_all(render(&self.message, info).as_bytes())?;
+
+        Ok(())
+    }
+}
+
+/// Unixドメインソケットに接続してメッセージを書き込むアクション
+#[cfg(unix)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UnixSocketAction {
+    /// ソケットのパス
+    pub path: PathBuf,
+    /// 書き込むメッセージ
+    pub message: String,
+}
+
+#[cfg(unix)]
+impl Action for UnixSocketAction {
+    fn execute(&self, info: &SwitchInfo) -> Result<()> {
+        let mut stream = std::os::unix::net::UnixStream::connect(&self.path)?;
+        stream.write_all(render(&self.message, info).as_bytes())?;
+
+        Ok(())
+    }
+}
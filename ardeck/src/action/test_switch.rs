@@ -0,0 +1,17 @@
+//! テスト用のスイッチの情報
+
+use crate::device::switch::{SwitchInfo, SwitchKind};
+
+pub fn switch(kind: SwitchKind, pin: u8, state: u16) -> SwitchInfo {
+    SwitchInfo {
+        kind,
+        pin,
+        state,
+        ..Default::default()
+    }
+}
+
+/// ピン0のアナログスイッチ
+pub fn analog(state: u16) -> SwitchInfo {
+    switch(SwitchKind::Analog, 0, state)
+}
@@ -0,0 +1,145 @@
+use std::{process::Command, sync::Mutex};
+
+use crate::action::{Error, Result, continuous::ContinuousAction};
+
+/// 音量を設定するバックエンド
+pub trait VolumeBackend: Send + Sync {
+    /// 音量を `0.0..=1.0` で設定する
+    fn set_volume(&self, volume: f32) -> Result<()>;
+}
+
+fn run(command: &mut Command) -> Result<()> {
+    let status = command.status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::Io(std::io::Error::other(format!(
+            "{:?} exited with {}",
+            command.get_program(),
+            status
+        ))))
+    }
+}
+
+/// PulseAudioの `pactl` で音量を設定するバックエンド
+#[derive(Debug, Clone)]
+pub struct PactlBackend {
+    /// 対象のシンク
+    pub sink: String,
+}
+
+impl Default for PactlBackend {
+    fn default() -> Self {
+        Self {
+            sink: "@DEFAULT_SINK@".into(),
+        }
+    }
+}
+
+impl VolumeBackend for PactlBackend {
+    fn set_volume(&self, volume: f32) -> Result<()> {
+        run(Command::new("pactl").args([
+            "set-sink-volume",
+            &self.sink,
+            &format!("{}%", (volume * 100.0).round()),
+        ]))
+    }
+}
+
+/// PipeWire(WirePlumber)の `wpctl` で音量を設定するバックエンド
+#[derive(Debug, Clone)]
+pub struct WpctlBackend {
+    /// 対象のノード
+    pub node: String,
+}
+
+impl Default for WpctlBackend {
+    fn default() -> Self {
+        Self {
+            node: "@DEFAULT_AUDIO_SINK@".into(),
+        }
+    }
+}
+
+impl VolumeBackend for WpctlBackend {
+    fn set_volume(&self, volume: f32) -> Result<()> {
+        run(Command::new("wpctl").args(["set-volume", &self.node, &format!("{:.2}", volume)]))
+    }
+}
+
+/// 設定された音量を記録するだけのバックエンド
+#[derive(Debug, Default)]
+pub struct MockVolumeBackend {
+    history: Mutex<Vec<f32>>,
+}
+
+impl MockVolumeBackend {
+    /// これまでに設定された音量の履歴
+    pub fn history(&self) -> Vec<f32> {
+        self.history
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl VolumeBackend for MockVolumeBackend {
+    fn set_volume(&self, volume: f32) -> Result<()> {
+        self.history
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(volume);
+        Ok(())
+    }
+}
+
+/// 値に応じて音量を設定するアクション
+pub struct VolumeAction<B> {
+    backend: B,
+}
+
+impl<B: VolumeBackend> VolumeAction<B> {
+    pub fn new(backend: B) -> Self {
+        Self { backend }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+}
+
+impl<B: VolumeBackend> ContinuousAction for VolumeAction<B> {
+    fn update(&self, value: f32) -> Result<()> {
+        self.backend.set_volume(value.clamp(0.0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::action::{Action, continuous::RateLimited, test_switch::analog};
+
+    #[test]
+    fn rate_limited_volume() {
+        let action = RateLimited::new(
+            VolumeAction::new(MockVolumeBackend::default()),
+            Duration::from_secs(60),
+            0.05,
+        );
+
+        action.execute(&analog(512)).unwrap();
+        // 間隔内の値は捨てられる
+        action.execute(&analog(600)).unwrap();
+        // 端の値は常に反映される
+        action.execute(&analog(1023)).unwrap();
+        action.execute(&analog(1023)).unwrap();
+        action.execute(&analog(0)).unwrap();
+
+        let history = action.inner().backend().history();
+        assert_eq!(history.len(), 3);
+        assert!((history[0] - 512.0 / 1023.0).abs() < f32::EPSILON);
+        assert_eq!(history[1..], [1.0, 0.0]);
+    }
+}
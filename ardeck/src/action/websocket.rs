@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use tungstenite::Message;
+
+use crate::{
+    action::{Action, Error, Result, render},
+    device::switch::SwitchInfo,
+};
+
+/// WebSocketでテキストメッセージを送信するアクション
+///
+/// 実行ごとに接続し、送信後に切断します。
+/// メッセージには [`render`] のテンプレートが使えます。
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketAction {
+    /// 接続先のURL ex: `ws://localhost:4455`
+    pub url: String,
+    /// 送信するメッセージ
+    pub message: String,
+}
+
+impl Action for WebSocketAction {
+    fn execute(&self, info: &SwitchInfo) -> Result<()> {
+        let (mut socket, _) =
+            tungstenite::connect(&self.url).map_err(|e| Error::WebSocket(Box::new(e)))?;
+
+        socket
+            .send(Message::text(render(&self.message, info)))
+            .map_err(|e| Error::WebSocket(Box::new(e)))?;
+
+        if let Err(e) = socket.close(None) {
+            log::debug!("Failed close websocket: {}", e);
+        }
+
+        Ok(())
+    }
+}
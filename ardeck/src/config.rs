@@ -1,10 +1,15 @@
 use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
 
 /// 設定ファイルを定義する
 ///
 /// # Example
 ///
 /// ```
+/// use ardeck::config::ConfigFile;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Clone, Deserialize, Serialize)]
 /// struct MyConfig {
 ///     name: String,
 ///     age: u32,
@@ -27,4 +32,58 @@ use serde::{Serialize, de::DeserializeOwned};
 /// ```
 pub trait ConfigFile: Serialize + DeserializeOwned + Default + Clone + Send + Sync {
     fn name() -> &'static str;
+
+    /// 設定ファイルのスキーマのバージョン
+    ///
+    /// フィールドを追加・変更した時に1つ増やし、[`ConfigFile::migrate`] に古いバージョンからの変換を追加します。
+    /// 0の時はバージョンを保存しません。
+    fn version() -> u32 {
+        0
+    }
+
+    /// バージョン `from` のデータを `from + 1` のデータに変換する
+    ///
+    /// 読み込んだファイルのバージョンが古い場合、現在のバージョンになるまで順に呼ばれます。
+    /// バージョンが保存されていないファイルはバージョン0とみなされます。
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use ardeck::config::ConfigFile;
+    /// use serde::{Deserialize, Serialize};
+    /// use serde_json::Value;
+    ///
+    /// #[derive(Clone, Default, Deserialize, Serialize)]
+    /// struct MyConfig {
+    ///     // バージョン0では `age` だった
+    ///     years: u32,
+    /// }
+    ///
+    /// impl ConfigFile for MyConfig {
+    ///     fn name() -> &'static str {
+    ///         "my_config.json"
+    ///     }
+    ///
+    ///     fn version() -> u32 {
+    ///         1
+    ///     }
+    ///
+    ///     fn migrate(from: u32, mut value: Value) -> Value {
+    ///         if from == 0
+    ///             && let Some(map) = value.as_object_mut()
+    ///             && let Some(age) = map.remove("age")
+    ///         {
+    ///             map.insert("years".into(), age);
+    ///         }
+    ///         value
+    ///     }
+    /// }
+    ///
+    /// let value = MyConfig::migrate(0, serde_json::json!({ "age": 42 }));
+    /// assert_eq!(value, serde_json::json!({ "years": 42 }));
+    /// ```
+    fn migrate(from: u32, value: Value) -> Value {
+        let _ = from;
+        value
+    }
 }
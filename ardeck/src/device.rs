@@ -1,23 +1,39 @@
+#[cfg(any(test, feature = "store"))]
+pub mod alias;
+mod daemon;
 pub mod decode;
+mod error;
+pub mod filter;
+pub mod handler;
+pub mod identity;
+pub mod manager;
 pub mod switch;
+#[cfg(test)]
+mod test_device;
+pub mod watcher;
 
-use std::{
-    default, fmt,
-    sync::{Arc, mpsc},
-    thread::{self, sleep},
-    time::Duration,
-};
+use std::{fmt, sync::Arc, time::Duration};
+
+pub use error::{DecodeError, DiscoveryError, Error, Result, SessionError, TransportError};
 
-use serialport::{SerialPort, SerialPortType, UsbPortInfo};
-use smol::lock::Mutex;
-use syn::token::Pub;
+use serde::{Deserialize, Serialize};
+use serialport::{SerialPortType, UsbPortInfo};
+use smol::{channel::Sender, lock::Mutex};
 
 use crate::device::{
-    decode::{Decoder, raw_to_switch_info},
+    daemon::Daemon,
+    handler::{
+        DEFAULT_PRIORITY, DEFAULT_TIME_BUDGET, Dispatcher, HandlerGuard, HandlerId, HandlerList,
+        HandlerStats,
+    },
+    identity::{DeviceIdentity, assign_command, find_collisions},
     switch::SwitchInfo,
 };
 
 /// デバイスのハードウェア固有番号を使用して、識別番号を作成する
+///
+/// シリアル番号を持たないデバイスではIDが重複することがあります。
+/// 重複は [`identity::find_collisions`] で検出できます。
 fn make_device_id(port_info: &UsbPortInfo) -> String {
     if let Some(serial_number) = &port_info.serial_number {
         format!(
@@ -30,7 +46,8 @@ fn make_device_id(port_info: &UsbPortInfo) -> String {
 }
 
 /// コンピューターに接続されて利用可能なシリアルポートデバイスの情報
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DeviceInfo {
     /// ポート名
     pub port_name: String,
@@ -40,72 +57,130 @@ pub struct DeviceInfo {
     pub device_id: String,
 }
 
+impl DeviceInfo {
+    /// USBデバイスが報告した製造元
+    pub fn manufacturer(&self) -> Option<&str> {
+        self.usb_port_info.manufacturer.as_deref()
+    }
+
+    /// USBデバイスが報告した製品名
+    pub fn product(&self) -> Option<&str> {
+        self.usb_port_info.product.as_deref()
+    }
+
+    /// 人が読むためのデバイス名
+    ///
+    /// 製品名、既知のデバイス名、ベンダーID・プロダクトIDの順に使用できるものを返します。
+    /// ユーザーが設定した別名を優先する場合は `alias::DeviceAliases::label` を使用します (`store` 機能)。
+    pub fn name(&self) -> String {
+        let info = &self.usb_port_info;
+
+        self.product()
+            .map(str::to_string)
+            .or_else(|| filter::known_device_name(info.vid, info.pid).map(str::to_string))
+            .unwrap_or_else(|| format!("{:04X}:{:04X}", info.vid, info.pid))
+    }
+
+    /// デバイス名とポート名をまとめたラベル ex: `Arduino Uno (/dev/ttyACM0)`
+    pub fn label(&self) -> String {
+        format!("{} ({})", self.name(), self.port_name)
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.label())
+    }
+}
+
 /// 接続可能なUSB Port一覧を取得する
 ///
+/// Arduino互換デバイスだけに絞り込むには [`filter::DeviceFilter`] を使用します。
+///
 /// # Example
 /// ```
+/// use ardeck::device::filter::DeviceFilter;
+///
 /// let device = ardeck::device::available_list();
+/// let arduino = DeviceFilter::arduino_compatible().apply(device);
 /// ```
 pub fn available_list() -> Vec<DeviceInfo> {
-    serialport::available_ports()
-        .unwrap_or(Vec::new())
+    try_available_list().unwrap_or_else(|e| {
+        log::error!("{}", e);
+        Vec::new()
+    })
+}
+
+/// 接続可能なUSB Port一覧を取得する。一覧を取得できなかった場合はエラーを返す
+pub fn try_available_list() -> Result<Vec<DeviceInfo>> {
+    let list: Vec<DeviceInfo> = serialport::available_ports()
+        .map_err(DiscoveryError::Enumerate)?
         .into_iter()
         .filter_map(|port| match &port.port_type {
             SerialPortType::UsbPort(e) => Some(DeviceInfo {
                 port_name: port.port_name.clone(),
                 usb_port_info: e.clone(),
-                device_id: make_device_id(&e),
+                device_id: make_device_id(e),
             }),
             _ => None,
         })
-        .collect()
-}
+        .collect();
+
+    for collision in find_collisions(&list) {
+        log::warn!(
+            "Device id `{}` collides between {:?}. Assign an id to the device.",
+            collision.device_id,
+            collision.port_names
+        );
+    }
 
-// TODO: 名前変える
-/// デバイス一覧の実装
-trait DeviceInfoList {
-    fn arduino_only(self) -> Vec<DeviceInfo>;
+    Ok(list)
 }
 
-impl DeviceInfoList for Vec<DeviceInfo> {
-    /// デバイス一覧のうち、arduinoのベンダーコードを持つデバイスだけを抽出する
-    /// # Example
-    /// ```
-    /// let device = ardeck::device::available_list().arduino_only();
-    /// ```
-    fn arduino_only(self) -> Vec<DeviceInfo> {
-        self.into_iter()
-            // 9025: Arduino LA のベンダーID
-            .filter(|port| port.usb_port_info.vid == 9025)
-            .collect()
-    }
+/// デバイスIDに一致する接続中のデバイスを探す
+pub fn find_device(device_id: &str) -> Result<DeviceInfo> {
+    try_available_list()?
+        .into_iter()
+        .find(|device| device.device_id == device_id)
+        .ok_or_else(|| {
+            DiscoveryError::NotFound {
+                device_id: device_id.to_string(),
+            }
+            .into()
+        })
 }
 
-#[derive(Debug, Clone)]
-enum SessionErrorKind {
-    InitializationError,
-    TimeOut,
-}
+/// デバイスIDから、デバイスが現在接続されているポートを `devices` の中から探す
+///
+/// `device_info` と同じポート名のデバイスがあればそれを返します。
+/// 同じデバイスIDのデバイスが複数あり、どれが元のデバイスか判別できない場合は、
+/// 他のセッションが使っているポートを選ばないように `None` を返します。
+fn resolve_port(device_info: &DeviceInfo, devices: Vec<DeviceInfo>) -> Option<DeviceInfo> {
+    let mut candidates: Vec<DeviceInfo> = devices
+        .into_iter()
+        .filter(|device| device.device_id == device_info.device_id)
+        .collect();
+
+    if let Some(same_port) = candidates
+        .iter()
+        .position(|device| device.port_name == device_info.port_name)
+    {
+        return Some(candidates.swap_remove(same_port));
+    }
 
-impl fmt::Display for SessionErrorKind {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::InitializationError => write!(f, "Failed initialization."),
-            Self::TimeOut => write!(f, "Timeout."),
-        }
+    if candidates.len() > 1 {
+        log::warn!(
+            "Device id `{}` matches {} ports, keep {}",
+            device_info.device_id,
+            candidates.len(),
+            device_info.port_name
+        );
+        return None;
     }
-}
 
-#[derive(Debug, Clone, thiserror::Error)]
-enum Error {
-    #[error("Session error: `{0}`")]
-    Session(SessionErrorKind),
-    #[error("Serialport error: `{0}`")]
-    Serialport(#[from] serialport::Error),
+    candidates.pop()
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
-
 #[derive(Debug, Clone, Default)]
 pub enum SessionEvent {
     /// 初回接続中、または再接続中
@@ -114,13 +189,36 @@ pub enum SessionEvent {
     Connected,
     /// データ受信した
     Data(SwitchInfo),
+    /// デバイスがIDを報告した
+    Identified(DeviceIdentity),
+    /// 再接続時にデバイスのポート名が変わっていた
+    PortChanged {
+        /// 変更前のポート名
+        from: String,
+        /// 変更後のポート名
+        to: String,
+    },
     /// 切断済み
     #[default]
     Disconnected,
+    /// 一時停止した。この時点でシリアルポートは閉じられている
+    Paused,
     /// 通信中にエラーが発生
     Error(Error),
 }
 
+impl SessionEvent {
+    /// 接続状況の変化を表すイベントか
+    ///
+    /// [`SessionEvent::Connecting`], [`SessionEvent::Connected`], [`SessionEvent::Disconnected`], [`SessionEvent::Paused`] が該当します。
+    pub fn is_connection_state(&self) -> bool {
+        matches!(
+            self,
+            Self::Connecting | Self::Connected | Self::Disconnected | Self::Paused
+        )
+    }
+}
+
 type ArdeckConnectionHandler = Box<dyn Fn(SessionEvent) + Send + Sync + 'static>;
 
 /// セッションを作成する前に設定をおこないます。
@@ -131,8 +229,10 @@ pub struct SessionBuilder {
     connect_attempt_limit: u16,
     /// 失敗した後の次の試行までの待機時間
     connect_retry_interval: Duration,
+    /// ハンドラー1回の実行にかけてよい時間
+    handler_time_budget: Duration,
 
-    handler: Vec<ArdeckConnectionHandler>,
+    handler: HandlerList,
 }
 
 impl SessionBuilder {
@@ -141,25 +241,49 @@ impl SessionBuilder {
             device_info,
             connect_attempt_limit: 0,
             connect_retry_interval: Duration::ZERO,
-            handler: Vec::new(),
+            handler_time_budget: DEFAULT_TIME_BUDGET,
+            handler: HandlerList::default(),
         }
     }
 
-    /// 接続の際に試行する最大回数
+    /// 接続の際に試行する最大回数。0の時は制限を設けない
+    ///
+    /// 連続して接続に失敗した回数が上限に達すると、[`SessionEvent::Error`] を発行してセッションを終了します。
     pub fn connect_attempt_limit(mut self, connect_attempt_limit: u16) -> Self {
         self.connect_attempt_limit = connect_attempt_limit;
         self
     }
 
-    /// 失敗した後の次の試行までの待機時間
+    /// 失敗した後の次の試行までの待機時間。指定しない場合は1秒
     pub fn connect_retry_interval(mut self, connect_retry_interval: Duration) -> Self {
         self.connect_retry_interval = connect_retry_interval;
         self
     }
 
+    /// ハンドラー1回の実行にかけてよい時間。指定しない場合は [`DEFAULT_TIME_BUDGET`]
+    ///
+    /// 超えた場合は警告をログに出力し、[`HandlerStats::slow_calls`] に数えます。
+    /// ハンドラーは読み込みとは別のスレッドで実行されるので、超えても受信は止まりません。
+    pub fn handler_time_budget(mut self, budget: Duration) -> Self {
+        self.handler_time_budget = budget;
+        self
+    }
+
     /// データを受信したときに実行するハンドラー
-    pub fn handler(mut self, handler: ArdeckConnectionHandler) -> Self {
-        self.handler.push(handler);
+    ///
+    /// ハンドラーがパニックした場合、セッションは継続し [`Error::HandlerPanicked`] のイベントが発行されます。
+    pub fn handler(self, handler: ArdeckConnectionHandler) -> Self {
+        self.handler_with_priority(handler, DEFAULT_PRIORITY)
+    }
+
+    /// 優先度を指定してハンドラーを追加する。優先度の高いハンドラーから順に実行されます
+    pub fn handler_with_priority(
+        mut self,
+        handler: ArdeckConnectionHandler,
+        priority: i32,
+    ) -> Self {
+        // ビルダーで追加したハンドラーはセッションが続く限り登録したままにする
+        self.handler.insert(handler, priority).detach();
         self
     }
 
@@ -169,17 +293,25 @@ impl SessionBuilder {
 }
 
 pub struct Session {
-    cmd_tx: Option<mpsc::Sender<SessionMessage>>,
+    cmd_tx: Option<Sender<SessionMessage>>,
+    /// デーモンのタスク
+    task: Option<smol::Task<()>>,
     // 接続中のデバイス情報
     device_info: DeviceInfo,
-    /// 接続状況
-    state: SessionEvent,
+    /// 現在接続しているポート名
+    port_name: Arc<Mutex<String>>,
     /// ハンドラー
-    handler: Arc<Mutex<Vec<ArdeckConnectionHandler>>>,
+    handler: Arc<Mutex<HandlerList>>,
+    /// デバイスが報告したID
+    identity: Arc<Mutex<Option<DeviceIdentity>>>,
     /// 接続試行時の試行回数の最大値 0の時は制限を設けない
     connect_attempt_limit: u16,
     /// 失敗した後の次の試行までの待機時間
     connect_retry_interval: Duration,
+    /// ハンドラー1回の実行にかけてよい時間
+    handler_time_budget: Duration,
+    /// ハンドラーの実行状況
+    handler_stats: Arc<HandlerStats>,
 }
 
 impl Session {
@@ -188,117 +320,177 @@ impl Session {
 
         Self {
             cmd_tx: None,
+            task: None,
+            port_name: Arc::new(Mutex::new(builder.device_info.port_name.clone())),
             device_info: builder.device_info,
-            state: SessionEvent::default(),
             handler: Arc::new(Mutex::new(builder.handler)),
+            identity: Arc::new(Mutex::new(None)),
             connect_attempt_limit: builder.connect_attempt_limit,
             connect_retry_interval: builder.connect_retry_interval,
+            handler_time_budget: builder.handler_time_budget,
+            handler_stats: Arc::new(HandlerStats::default()),
         }
     }
 
-    pub async fn add_handler(&mut self, handler: ArdeckConnectionHandler) {
-        self.handler.lock().await.push(handler);
+    /// ハンドラーを追加する
+    ///
+    /// 返されたガードをドロップすると登録が解除されます。
+    pub async fn add_handler(&self, handler: ArdeckConnectionHandler) -> HandlerGuard {
+        self.add_handler_with_priority(handler, DEFAULT_PRIORITY)
+            .await
+    }
+
+    /// 優先度を指定してハンドラーを追加する。優先度の高いハンドラーから順に実行されます
+    pub async fn add_handler_with_priority(
+        &self,
+        handler: ArdeckConnectionHandler,
+        priority: i32,
+    ) -> HandlerGuard {
+        self.handler.lock().await.insert(handler, priority)
+    }
+
+    /// ハンドラーの登録を解除する。登録されていなければ `false` を返す
+    pub async fn remove_handler(&self, id: HandlerId) -> bool {
+        self.handler.lock().await.remove(id)
+    }
+
+    /// 指定したピンのスイッチのデータだけを受け取るハンドラーを追加する
+    pub async fn on_data(
+        &self,
+        pin: u8,
+        handler: impl Fn(SwitchInfo) + Send + Sync + 'static,
+    ) -> HandlerGuard {
+        self.add_handler(handler::data_for_pin(pin, handler)).await
+    }
+
+    /// 接続状況の変化だけを受け取るハンドラーを追加する
+    pub async fn on_connection_state(
+        &self,
+        handler: impl Fn(SessionEvent) + Send + Sync + 'static,
+    ) -> HandlerGuard {
+        self.add_handler(handler::connection_state(handler)).await
     }
 
     pub fn start(&mut self) {
-        // 必要なものをクローンする
-        let device_info = self.device_info.clone();
-        let handler = self.handler.clone();
-        let connect_attempt_limit = self.connect_attempt_limit;
-        let connect_retry_interval = self.connect_retry_interval.clone();
-        let (msg_tx, msg_rx) = mpsc::channel::<SessionMessage>();
-        self.cmd_tx = Some(msg_tx);
-        smol::spawn(async move {
-            log::info!("daemon~!");
-            'threadloop: loop {
-                if let Ok(e) = msg_rx.try_recv() {
-                    // TODO: 受け取り部分を1か所にまとめる
-                    // TODO: handler
-                    match e {
-                        SessionMessage::Drop => break,
-                    }
-                }
-
-                // TODO: もし接続が切れたらthreadloopをcontinueする
-                let mut port = match serialport::new(&device_info.port_name, 9600).open() {
-                    Ok(p) => p,
-                    Err(e) => {
-                        log::error!("{}", e.to_string());
-                        smol::Timer::after(Duration::from_millis(1000)).await;
-                        // TODO: handler
-                        continue 'threadloop;
-                    }
-                };
+        if self.task.is_some() {
+            log::warn!("Session already started: {}", self.device_info.port_name);
+            return;
+        }
 
-                // ハンドラー発火
-                for handler in handler.lock().await.iter() {
-                    handler(SessionEvent::Connected);
-                }
+        let (msg_tx, msg_rx) = smol::channel::unbounded::<SessionMessage>();
+        self.cmd_tx = Some(msg_tx);
 
-                let mut decoder = Decoder::new();
+        let daemon = Daemon {
+            device_info: self.device_info.clone(),
+            port_name: self.port_name.clone(),
+            dispatcher: Dispatcher::spawn(
+                self.handler.clone(),
+                self.handler_time_budget,
+                self.handler_stats.clone(),
+            ),
+            identity: self.identity.clone(),
+            connect_attempt_limit: self.connect_attempt_limit,
+            connect_retry_interval: self.connect_retry_interval,
+            msg_rx,
+        };
+        self.task = Some(smol::spawn(daemon.run()));
+    }
 
-                // 接続時のポート情報要求
-                port.write(&[0xFF]).unwrap();
+    /// セッションを終了する
+    ///
+    /// デーモンの終了を待つので、戻った時点でシリアルポートは解放され、
+    /// 最後の [`SessionEvent::Disconnected`] がハンドラーに届いています。
+    ///
+    /// 読み込みスレッドはタイムアウトごとに終了を確認するため、戻るまでに最大で約100msかかります。
+    pub async fn stop(&mut self) {
+        if let Some(cmd_tx) = self.cmd_tx.take() {
+            // 送信に失敗した場合はデーモンが既に終了している
+            let _ = cmd_tx.try_send(SessionMessage::Drop);
+        }
 
-                // readloop
-                loop {
-                    if let Ok(e) = msg_rx.try_recv() {
-                        match e {
-                            // TODO: handler
-                            SessionMessage::Drop => break 'threadloop,
-                        }
-                    }
+        if let Some(task) = self.task.take() {
+            task.await;
+        }
+    }
 
-                    let mut buf: [u8; 16] = [0; 16];
-
-                    match port.read(&mut buf) {
-                        Ok(len) => {
-                            log::debug!("received: {:?} ({} bytes)", &buf[0..len], len);
-                            decoder.receive(&buf[0..len]);
-
-                            while let Some(data) = decoder.process_buffer() {
-                                log::debug!("decoded data!!! {:?}", data);
-                                let Some(data) = raw_to_switch_info(&data) else {
-                                    // パース失敗
-                                    log::error!("Failed parse to switch info: {:?}", data);
-                                    continue;
-                                };
-
-                                log::debug!("{:?}", data);
-
-                                // ハンドラー発火
-                                for handler in handler.lock().await.iter() {
-                                    log::debug!("hhhhhh");
-                                    handler(SessionEvent::Data(data.clone()));
-                                }
-                            }
-                        }
-                        Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                            // TODO: handler
-                            // NOTE: 単純のタイムアウトの時は切断しないように修正
-                            continue;
-                        }
-                        Err(e) => {
-                            // TODO: handler
-                            continue 'threadloop;
-                        }
-                    };
-                }
-            }
-        })
-        .detach();
+    /// ハンドラーの実行状況
+    pub fn handler_stats(&self) -> &HandlerStats {
+        &self.handler_stats
     }
 
     pub fn device_info(&self) -> &DeviceInfo {
         &self.device_info
     }
+
+    /// 現在接続しているポート名
+    ///
+    /// 再接続でポート名が変わった場合は [`Session::device_info`] のポート名とは異なります。
+    pub async fn port_name(&self) -> String {
+        self.port_name.lock().await.clone()
+    }
+
+    /// デバイスを識別するID
+    ///
+    /// デバイスがハンドシェイクでIDを報告していればそのIDを、そうでなければUSBのポート情報から生成されたIDを返します。
+    pub async fn identity(&self) -> DeviceIdentity {
+        self.identity
+            .lock()
+            .await
+            .clone()
+            .unwrap_or_else(|| DeviceIdentity::usb(&self.device_info))
+    }
+
+    /// IDをデバイスのEEPROMに書き込む
+    ///
+    /// 書き込み後にポート情報を再要求するので、成功すれば [`SessionEvent::Identified`] が発行されます。
+    /// 新しいIDは [`identity::generate_id`] で生成できます。
+    pub fn assign_device_id(&self, id: &str) -> Result<()> {
+        let command =
+            assign_command(id).ok_or_else(|| SessionError::InvalidDeviceId(id.to_string()))?;
+
+        self.send(SessionMessage::Write(command))?;
+        self.send(SessionMessage::Write(vec![0xFF]))
+    }
+
+    /// シリアルポートを閉じて一時停止する
+    ///
+    /// 設定とハンドラーは保持されたままです。ファームウェアの書き込みなどで一時的にポートを解放したいときに使用します。
+    /// ポートが閉じられると [`SessionEvent::Paused`] が発行されます。
+    pub fn pause(&self) -> Result<()> {
+        self.send(SessionMessage::Pause)
+    }
+
+    /// 一時停止したセッションを再開する
+    pub fn resume(&self) -> Result<()> {
+        self.send(SessionMessage::Resume)
+    }
+
+    /// シリアルポートを閉じて接続し直す。一時停止中であれば再開する
+    pub fn restart(&self) -> Result<()> {
+        self.send(SessionMessage::Restart)
+    }
+
+    fn send(&self, message: SessionMessage) -> Result<()> {
+        self.cmd_tx
+            .as_ref()
+            .ok_or(SessionError::NotStarted)?
+            .try_send(message)
+            .map_err(|_| SessionError::NotStarted.into())
+    }
 }
 
 impl Drop for Session {
     fn drop(&mut self) {
-        if let Some(cmd_tx) = &self.cmd_tx {
+        if let Some(cmd_tx) = self.cmd_tx.take() {
             log::debug!("Dropped: {}", self.device_info.port_name);
-            cmd_tx.send(SessionMessage::Drop).unwrap();
+            if cmd_tx.try_send(SessionMessage::Drop).is_err() {
+                log::debug!("Daemon already exited: {}", self.device_info.port_name);
+            }
+        }
+
+        // 終了処理を最後までおこなうため、タスクはキャンセルせずに切り離す
+        if let Some(task) = self.task.take() {
+            task.detach();
         }
     }
 }
@@ -306,9 +498,109 @@ impl Drop for Session {
 /// SessionがDaemonに送信するメッセージ
 enum SessionMessage {
     Drop,
+    /// デバイスへバイト列を書き込む
+    Write(Vec<u8>),
+    /// ポートを閉じて一時停止する
+    Pause,
+    /// 一時停止を解除する
+    Resume,
+    /// ポートを閉じて接続し直す
+    Restart,
 }
 
 // DRAFT:
 // - コネクションインスタンスが生成されると接続先を記録したインスタンスが生成される
 // - インスタンスが存在する間はシリアルポートが切断されても再接続を試みる
 // - 初回接続時に未接続ならばリトライ・アクセス拒否ならば初期化失敗としてインスタンスを生成しない
+
+#[cfg(test)]
+mod tests {
+    use super::{test_device::device, *};
+
+    #[test]
+    fn resolve() {
+        let session = device("/dev/ttyACM0", "A");
+
+        // 同じポートにあればそのまま
+        assert_eq!(
+            resolve_port(&session, vec![device("/dev/ttyACM0", "A")]),
+            Some(session.clone())
+        );
+        // 1台だけならポート名が変わっていても追いかける
+        assert_eq!(
+            resolve_port(
+                &session,
+                vec![device("/dev/ttyACM2", "B"), device("/dev/ttyACM1", "A")]
+            ),
+            Some(device("/dev/ttyACM1", "A"))
+        );
+        // 同じデバイスIDが複数あれば元のポートを優先する
+        assert_eq!(
+            resolve_port(
+                &session,
+                vec![device("/dev/ttyACM1", "A"), device("/dev/ttyACM0", "A")]
+            ),
+            Some(session.clone())
+        );
+        // どれが元のデバイスか判別できない
+        assert_eq!(
+            resolve_port(
+                &session,
+                vec![device("/dev/ttyACM1", "A"), device("/dev/ttyACM2", "A")]
+            ),
+            None
+        );
+        assert_eq!(resolve_port(&session, vec![]), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stop_latency() {
+        use std::time::Instant;
+
+        use serialport::{SerialPort, TTYPort};
+
+        let (_master, slave) = TTYPort::pair().unwrap();
+        let port_name = slave.name().unwrap();
+        // セッションが排他的に開けるように閉じておく
+        drop(slave);
+
+        let (tx, rx) = smol::channel::unbounded();
+        let mut session = SessionBuilder::new(device(&port_name, "pty"))
+            .connect_attempt_limit(1)
+            .handler(Box::new(move |event| {
+                let _ = tx.try_send(event);
+            }))
+            .build();
+        session.start();
+
+        smol::block_on(async {
+            let connected = smol::future::or(
+                async {
+                    while let Ok(event) = rx.recv().await {
+                        if let SessionEvent::Connected = event {
+                            return true;
+                        }
+                    }
+                    false
+                },
+                async {
+                    smol::Timer::after(Duration::from_secs(5)).await;
+                    false
+                },
+            )
+            .await;
+            assert!(connected, "Failed connect to {}", port_name);
+
+            let start = Instant::now();
+            session.stop().await;
+            let elapsed = start.elapsed();
+
+            assert!(
+                elapsed < daemon::READ_TIMEOUT * 2,
+                "stop took {:?}",
+                elapsed
+            );
+        });
+    }
+}
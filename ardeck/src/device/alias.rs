@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::ConfigFile, device::DeviceInfo, store::StoreTrait};
+
+/// ユーザーがデバイスに設定した表示情報
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceAlias {
+    /// 別名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// アイコン。絵文字や画像のパスなど、表示側で解釈する
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+}
+
+/// デバイスIDごとの表示情報の一覧
+///
+/// # Example
+///
+/// ```no_run
+/// use ardeck::{
+///     device::{alias::DeviceAliases, available_list},
+///     store::{Store, StoreTrait},
+/// };
+///
+/// let store = Store::new("./config").unwrap();
+/// let mut aliases = DeviceAliases::load(&store).unwrap_or_default();
+/// for device in available_list() {
+///     aliases.set_alias(&device.device_id, "Left deck");
+///     println!("{}", aliases.label(&device));
+/// }
+/// aliases.save(&store).unwrap();
+/// ```
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceAliases {
+    /// デバイスIDと表示情報の対応
+    pub devices: BTreeMap<String, DeviceAlias>,
+}
+
+impl ConfigFile for DeviceAliases {
+    fn name() -> &'static str {
+        "device_aliases.json"
+    }
+}
+
+impl StoreTrait for DeviceAliases {}
+
+impl DeviceAliases {
+    pub fn get(&self, device_id: &str) -> Option<&DeviceAlias> {
+        self.devices.get(device_id)
+    }
+
+    /// 別名を設定する
+    pub fn set_alias(&mut self, device_id: &str, alias: impl Into<String>) {
+        self.devices.entry(device_id.to_string()).or_default().alias = Some(alias.into());
+    }
+
+    /// アイコンを設定する
+    pub fn set_icon(&mut self, device_id: &str, icon: impl Into<String>) {
+        self.devices.entry(device_id.to_string()).or_default().icon = Some(icon.into());
+    }
+
+    /// 別名を削除する。アイコンも設定されていなければ表示情報ごと削除する
+    pub fn clear_alias(&mut self, device_id: &str) {
+        if let Some(entry) = self.devices.get_mut(device_id) {
+            entry.alias = None;
+            if entry.icon.is_none() {
+                self.devices.remove(device_id);
+            }
+        }
+    }
+
+    /// 表示情報を削除する
+    pub fn remove(&mut self, device_id: &str) -> Option<DeviceAlias> {
+        self.devices.remove(device_id)
+    }
+
+    /// 別名が設定されていれば別名を、なければ [`DeviceInfo::label`] を返す
+    pub fn label(&self, device: &DeviceInfo) -> String {
+        self.get(&device.device_id)
+            .and_then(|alias| alias.alias.clone())
+            .unwrap_or_else(|| device.label())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serialport::UsbPortInfo;
+
+    use super::*;
+
+    fn device() -> DeviceInfo {
+        DeviceInfo {
+            port_name: "/dev/ttyACM0".into(),
+            usb_port_info: UsbPortInfo {
+                vid: 0x2341,
+                pid: 0x0043,
+                serial_number: None,
+                manufacturer: None,
+                product: Some("Arduino Uno".into()),
+            },
+            device_id: "2341-0043".into(),
+        }
+    }
+
+    #[test]
+    fn set_and_clear() {
+        let device = device();
+        let mut aliases = DeviceAliases::default();
+        assert_eq!(aliases.label(&device), "Arduino Uno (/dev/ttyACM0)");
+
+        aliases.set_alias(&device.device_id, "Left deck");
+        assert_eq!(aliases.label(&device), "Left deck");
+
+        aliases.clear_alias(&device.device_id);
+        assert_eq!(aliases.label(&device), "Arduino Uno (/dev/ttyACM0)");
+        assert_eq!(aliases.get(&device.device_id), None);
+
+        // アイコンは別名を削除しても残る
+        aliases.set_alias(&device.device_id, "Left deck");
+        aliases.set_icon(&device.device_id, "🎛");
+        aliases.clear_alias(&device.device_id);
+        assert_eq!(
+            aliases.get(&device.device_id),
+            Some(&DeviceAlias {
+                alias: None,
+                icon: Some("🎛".into()),
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(&aliases).unwrap(),
+            serde_json::json!({ "devices": { "2341-0043": { "icon": "🎛" } } })
+        );
+    }
+}
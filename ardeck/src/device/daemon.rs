@@ -0,0 +1,328 @@
+use std::{
+    io::{self, Read},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serialport::SerialPort;
+use smol::{channel::Receiver, lock::Mutex};
+
+use crate::device::{
+    DecodeError, DeviceInfo, Error, SessionError, SessionEvent, SessionMessage, TransportError,
+    available_list,
+    decode::{Decoder, raw_to_switch_info},
+    handler::Dispatcher,
+    identity::{DeviceIdentity, parse_report},
+    resolve_port,
+};
+
+/// 読み込みスレッドがポートを確認する間隔
+///
+/// 読み込みスレッドの終了はこの時間だけ遅れることがあります。
+/// [`Session::stop`](super::Session::stop) などでポートを閉じる時の待ち時間の上限になります。
+pub(super) const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// 再試行の待機時間が指定されていない場合の待機時間
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// シリアルポートのブロッキング読み込みを専用スレッドでおこない、結果をチャンネルへ送る
+struct Reader {
+    rx: Receiver<io::Result<Vec<u8>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Reader {
+    fn spawn(mut port: Box<dyn SerialPort>) -> Self {
+        let (tx, rx) = smol::channel::unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+
+        let thread = thread::spawn(move || {
+            let mut buf = [0u8; 64];
+
+            while !stop_flag.load(Ordering::Relaxed) {
+                match port.read(&mut buf) {
+                    Ok(0) => continue,
+                    Ok(len) => {
+                        if tx.send_blocking(Ok(buf[..len].to_vec())).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                    Err(e) => {
+                        let _ = tx.send_blocking(Err(e));
+                        break;
+                    }
+                }
+            }
+        });
+
+        Self {
+            rx,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// 読み込みスレッドを終了し、スレッドが持つポートが閉じられるまで待つ
+    async fn close(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(thread) = self.thread.take()
+            && smol::unblock(move || thread.join()).await.is_err()
+        {
+            log::error!("Reader thread panicked");
+        }
+    }
+}
+
+/// デーモンが待機中に受け取る入力
+enum Input {
+    Message(Option<SessionMessage>),
+    Read(Option<io::Result<Vec<u8>>>),
+}
+
+/// セッションの接続を維持し、受信したデータをハンドラーへ送る
+///
+/// 制御メッセージとポートからの読み込みを同時に待つので、ポーリングせずにすぐ応答できます。
+pub(super) struct Daemon {
+    pub(super) device_info: DeviceInfo,
+    pub(super) port_name: Arc<Mutex<String>>,
+    pub(super) dispatcher: Dispatcher,
+    pub(super) identity: Arc<Mutex<Option<DeviceIdentity>>>,
+    pub(super) connect_attempt_limit: u16,
+    pub(super) connect_retry_interval: Duration,
+    pub(super) msg_rx: Receiver<SessionMessage>,
+}
+
+impl Daemon {
+    fn emit(&self, event: SessionEvent) {
+        self.dispatcher.emit(event);
+    }
+
+    fn write_error(&self, e: io::Error) -> Error {
+        TransportError::Write {
+            port_name: self.device_info.port_name.clone(),
+            source: Arc::new(e),
+        }
+        .into()
+    }
+
+    /// 再試行まで待機する。待機中に終了を求められたら `false` を返す
+    async fn wait_retry(&self, paused: &mut bool) -> bool {
+        let interval = if self.connect_retry_interval.is_zero() {
+            DEFAULT_RETRY_INTERVAL
+        } else {
+            self.connect_retry_interval
+        };
+
+        let message = smol::future::or(async { Some(self.msg_rx.recv().await.ok()) }, async {
+            smol::Timer::after(interval).await;
+            None
+        })
+        .await;
+
+        match message {
+            None => true,
+            Some(None | Some(SessionMessage::Drop)) => false,
+            Some(Some(SessionMessage::Pause)) => {
+                *paused = true;
+                self.emit(SessionEvent::Paused);
+                true
+            }
+            // 未接続のため書き込めない
+            Some(Some(SessionMessage::Write(_))) => true,
+            Some(Some(SessionMessage::Resume | SessionMessage::Restart)) => true,
+        }
+    }
+
+    /// 再接続時にポート名が変わっていることがあるので、デバイスIDから探し直す
+    async fn resolve_port(&mut self) {
+        // ポートの列挙はブロックするので、専用のスレッドでおこなう
+        let devices = smol::unblock(available_list).await;
+        if let Some(found) = resolve_port(&self.device_info, devices)
+            && found.port_name != self.device_info.port_name
+        {
+            log::info!(
+                "Port changed: {} -> {}",
+                self.device_info.port_name,
+                found.port_name
+            );
+            let event = SessionEvent::PortChanged {
+                from: self.device_info.port_name.clone(),
+                to: found.port_name.clone(),
+            };
+            *self.port_name.lock().await = found.port_name.clone();
+            self.device_info = found;
+
+            self.emit(event);
+        }
+    }
+
+    async fn receive(&self, decoder: &mut Decoder, bytes: &[u8]) {
+        log::debug!("received: {:?} ({} bytes)", bytes, bytes.len());
+        decoder.receive(bytes);
+
+        while let Some(data) = decoder.next_frame() {
+            let data = match data {
+                Ok(data) => data,
+                Err(e) => {
+                    log::error!("{}", e);
+                    self.emit(SessionEvent::Error(e.into()));
+                    continue;
+                }
+            };
+
+            log::debug!("decoded data!!! {:?}", data);
+            if let Some(reported) = parse_report(&data) {
+                log::info!("Device identified: {:?}", reported);
+                *self.identity.lock().await = Some(reported.clone());
+
+                self.emit(SessionEvent::Identified(reported));
+                continue;
+            }
+
+            let Some(data) = raw_to_switch_info(&data) else {
+                // パース失敗
+                log::error!("Failed parse to switch info: {:?}", data);
+                self.emit(SessionEvent::Error(DecodeError::Payload(data).into()));
+                continue;
+            };
+
+            log::debug!("{:?}", data);
+
+            // ハンドラー発火
+            self.emit(SessionEvent::Data(data));
+        }
+    }
+
+    pub(super) async fn run(mut self) {
+        log::info!("daemon~!");
+        // 一時停止中はポートを開かない
+        let mut paused = false;
+        // 連続して接続に失敗した回数
+        let mut attempts: u16 = 0;
+
+        'threadloop: loop {
+            // 一時停止中はメッセージが届くまで待つ
+            while paused {
+                match self.msg_rx.recv().await {
+                    Err(_) | Ok(SessionMessage::Drop) => break 'threadloop,
+                    Ok(SessionMessage::Resume | SessionMessage::Restart) => paused = false,
+                    Ok(SessionMessage::Pause | SessionMessage::Write(_)) => {}
+                }
+            }
+
+            self.resolve_port().await;
+            self.emit(SessionEvent::Connecting);
+
+            let port = serialport::new(&self.device_info.port_name, 9600)
+                .timeout(READ_TIMEOUT)
+                .open()
+                .and_then(|port| Ok((port.try_clone()?, port)));
+
+            let (reader, mut port) = match port {
+                Ok((reader, port)) => (Reader::spawn(reader), port),
+                Err(e) => {
+                    log::error!("{}", e);
+                    attempts = attempts.saturating_add(1);
+
+                    if self.connect_attempt_limit != 0 && attempts >= self.connect_attempt_limit {
+                        let source = TransportError::Open {
+                            port_name: self.device_info.port_name.clone(),
+                            source: e,
+                        };
+                        self.emit(SessionEvent::Error(
+                            SessionError::AttemptLimitReached { attempts, source }.into(),
+                        ));
+                        break 'threadloop;
+                    }
+
+                    if !self.wait_retry(&mut paused).await {
+                        break 'threadloop;
+                    }
+                    continue 'threadloop;
+                }
+            };
+            attempts = 0;
+
+            // ハンドラー発火
+            self.emit(SessionEvent::Connected);
+
+            let mut decoder = Decoder::new();
+
+            // 接続時のポート情報要求
+            if let Err(e) = port.write_all(&[0xFF]) {
+                log::error!("Failed request port info: {}", e);
+                self.emit(SessionEvent::Error(self.write_error(e)));
+            }
+
+            // readloop
+            loop {
+                let input = smol::future::or(
+                    async { Input::Message(self.msg_rx.recv().await.ok()) },
+                    async { Input::Read(reader.rx.recv().await.ok()) },
+                )
+                .await;
+
+                match input {
+                    Input::Message(None | Some(SessionMessage::Drop)) => {
+                        drop(port);
+                        reader.close().await;
+                        break 'threadloop;
+                    }
+                    Input::Message(Some(SessionMessage::Write(bytes))) => {
+                        if let Err(e) = port.write_all(&bytes) {
+                            log::error!("Failed write to device: {}", e);
+                            self.emit(SessionEvent::Error(self.write_error(e)));
+                        }
+                    }
+                    Input::Message(Some(SessionMessage::Pause)) => {
+                        // ポートを閉じてから通知する
+                        drop(port);
+                        reader.close().await;
+                        paused = true;
+                        log::info!("Session paused: {}", self.device_info.port_name);
+                        self.emit(SessionEvent::Paused);
+                        continue 'threadloop;
+                    }
+                    Input::Message(Some(SessionMessage::Restart)) => {
+                        drop(port);
+                        reader.close().await;
+                        log::info!("Session restarting: {}", self.device_info.port_name);
+                        self.emit(SessionEvent::Disconnected);
+                        continue 'threadloop;
+                    }
+                    Input::Message(Some(SessionMessage::Resume)) => {}
+                    Input::Read(Some(Ok(bytes))) => {
+                        self.receive(&mut decoder, &bytes).await;
+                    }
+                    Input::Read(result) => {
+                        if let Some(Err(e)) = result {
+                            log::error!("Disconnected: {}", e);
+                            let error = TransportError::Read {
+                                port_name: self.device_info.port_name.clone(),
+                                source: Arc::new(e),
+                            };
+                            self.emit(SessionEvent::Error(error.into()));
+                        }
+                        drop(port);
+                        reader.close().await;
+                        self.emit(SessionEvent::Disconnected);
+                        continue 'threadloop;
+                    }
+                }
+            }
+        }
+
+        // ループを抜けた時点でポートは閉じられている
+        log::info!("Session stopped: {}", self.device_info.port_name);
+        self.emit(SessionEvent::Disconnected);
+        self.dispatcher.close().await;
+    }
+}
@@ -1,16 +1,12 @@
-use std::fmt::Display;
-
-use chrono::{Local, Utc};
-
-use crate::device::switch::SwitchInfo;
-
-// TODO: エラー列挙作る？
+use crate::device::{error::DecodeError, switch::SwitchInfo};
 
 /// cobs形式のデータを生のバイト列へデコードします。失敗したら `None` を返します。
 ///
 /// # Example
 ///
 /// ```
+/// use ardeck::device::decode::dec_cobs;
+///
 /// assert_eq!(dec_cobs(vec![01, 01, 00]), Some(vec![00]));
 /// assert_eq!(dec_cobs(vec![01, 01, 01, 00]), Some(vec![00, 00]));
 /// assert_eq!(dec_cobs(vec![01, 02, 11, 01, 00]), Some(vec![00, 11, 00]));
@@ -19,9 +15,9 @@ use crate::device::switch::SwitchInfo;
 ///     Some(vec![11, 22, 00, 33])
 /// );
 /// ```
-fn dec_cobs(cobs_bytes: impl AsRef<[u8]>) -> Option<Vec<u8>> {
+pub fn dec_cobs(cobs_bytes: impl AsRef<[u8]>) -> Option<Vec<u8>> {
     let mut cobs_bytes = cobs_bytes.as_ref().to_vec();
-    if *cobs_bytes.last()? != 0 {
+    if cobs_bytes.len() < 2 || *cobs_bytes.last()? != 0 {
         return None;
     }
 
@@ -50,13 +46,13 @@ pub fn raw_to_switch_info(bytes: impl AsRef<[u8]>) -> Option<SwitchInfo> {
     let bytes = bytes.as_ref().to_vec();
 
     #[cfg(not(test))]
-    let timestamp_micros = Utc::now().timestamp_micros();
+    let timestamp_micros = chrono::Utc::now().timestamp_micros();
 
     #[cfg(test)]
     let timestamp_micros = 0;
 
     // switch kind
-    match bytes.get(0)? & 0x80 {
+    match bytes.first()? & 0x80 {
         // Digital Switch
         0 => {
             if bytes.len() == 1 {
@@ -71,7 +67,7 @@ pub fn raw_to_switch_info(bytes: impl AsRef<[u8]>) -> Option<SwitchInfo> {
             }
         }
         // Analog Switch
-        1 => {
+        0x80 => {
             if bytes.len() == 2 {
                 Some(SwitchInfo {
                     kind: super::switch::SwitchKind::Analog,
@@ -89,65 +85,60 @@ pub fn raw_to_switch_info(bytes: impl AsRef<[u8]>) -> Option<SwitchInfo> {
     // Some(info)
 }
 
+#[derive(Default)]
 pub struct Decoder {
     buf: Vec<u8>,
 }
 
 impl Decoder {
     pub fn new() -> Self {
-        Self { buf: Vec::new() }
+        Self::default()
     }
 
     /// COBSエンコードされたバイトデータを蓄積する
     pub fn receive(&mut self, data: &[u8]) {
-        self.buf.append(&mut data.as_ref().to_vec());
+        self.buf.extend_from_slice(data);
     }
 
     /// 蓄積されたバイトデータをCOBSエンコードする。
     ///
     /// 1度デコードが完了した時点で完成品を返却します。
-    /// デコードに失敗したら[`None`]が返ります。
+    /// デコードに失敗したら[`None`]が返ります。失敗の理由が必要な場合は [`Decoder::next_frame`] を使用します。
     pub fn process_buffer(&mut self) -> Option<Vec<u8>> {
-        // 0までを切り取ってスライスにする。なければNoneを返す
-        let mut buf = self
-            .buf
-            .drain(0..=self.buf.iter().position(|x| *x == 0)?)
-            .as_slice()
-            .to_vec();
-
-        log::trace!("Found one set: {:?}", buf);
+        self.next_frame()?.ok()
+    }
 
-        // 切り取ったデータをデコードする
-        let mut i = 0;
-        loop {
-            let i_val = *buf.get(i)?;
+    /// 蓄積されたバイトデータから1フレームを取り出してデコードする
+    ///
+    /// 区切りの `0` がまだ届いていなければ [`None`] を返します。
+    /// 不正なフレームは取り除かれるので、続けて呼ぶと次のフレームをデコードします。
+    pub fn next_frame(&mut self) -> Option<Result<Vec<u8>, DecodeError>> {
+        // 0までを切り取る。なければNoneを返す
+        let end = self.buf.iter().position(|x| *x == 0)?;
+        let frame: Vec<u8> = self.buf.drain(0..=end).collect();
 
-            buf[i] = 0;
+        log::trace!("Found one set: {:?}", frame);
 
-            if i_val == 0 {
-                break;
-            } else {
-                i += i_val as usize;
-            }
-        }
-
-        let buf = &buf[1..buf.len() - 1];
+        // 切り取ったデータをデコードする
+        let Some(buf) = dec_cobs(&frame) else {
+            return Some(Err(DecodeError::Cobs(frame)));
+        };
 
         log::trace!("Decoded: {:?}", buf);
 
         // チェックサム
-        let sum = buf.last()?; // 受け取った計算済み合計値
-        let payload = &buf[0..buf.len() - 1]; // 受け取ったデータのペイロード
-        let mut now_sum: u8 = 0; // 今から計算する合計値
-        for byte in payload.iter() {
-            now_sum = now_sum.wrapping_add(*byte);
-        }
-
-        if *sum == now_sum {
-            Some(payload.to_vec())
+        let Some((&expected, payload)) = buf.split_last() else {
+            return Some(Err(DecodeError::Cobs(frame)));
+        };
+        let actual = payload
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+        if expected == actual {
+            Some(Ok(payload.to_vec()))
         } else {
-            log::debug!("SUM error: {} != {}", sum, now_sum);
-            None
+            log::debug!("SUM error: {} != {}", expected, actual);
+            Some(Err(DecodeError::Checksum { expected, actual }))
         }
     }
 
@@ -160,61 +151,67 @@ impl Decoder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::device::switch::SwitchKind;
 
     #[test]
     fn dec() {
-        // dec_cobs test
-        // assert_eq!(dec_cobs(vec![01, 01, 00]), Some(vec![00]));
-        // assert_eq!(dec_cobs(vec![01, 01, 01, 00]), Some(vec![00, 00]));
-        // assert_eq!(dec_cobs(vec![01, 02, 11, 01, 00]), Some(vec![00, 11, 00]));
-        // assert_eq!(
-        //     dec_cobs(vec![03, 11, 22, 02, 33, 00]),
-        //     Some(vec![11, 22, 00, 33])
-        // );
-
-        // // raw_to_switch_info test
-        // // FIXME: timestampは除かないといけない
-        // assert_eq!(
-        //     raw_to_switch_info(vec![0b00000000]),
-        //     Some(SwitchInfo {
-        //         ..Default::default()
-        //     })
-        // );
-        // assert_eq!(
-        //     raw_to_switch_info(vec![0b00000011]),
-        //     Some(SwitchInfo {
-        //         pin: 1,
-        //         state: 1,
-        //         ..Default::default()
-        //     })
-        // );
+        assert_eq!(dec_cobs(vec![1, 1, 0]), Some(vec![0]));
+        assert_eq!(
+            dec_cobs(vec![3, 11, 22, 2, 33, 0]),
+            Some(vec![11, 22, 0, 33])
+        );
+        assert_eq!(dec_cobs(vec![0]), None);
+
+        assert_eq!(
+            raw_to_switch_info(vec![0b00000011]),
+            Some(SwitchInfo {
+                pin: 1,
+                state: 1,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            raw_to_switch_info(vec![0b10000101, 0x10]),
+            Some(SwitchInfo {
+                kind: SwitchKind::Analog,
+                pin: 1,
+                state: 0x110,
+                ..Default::default()
+            })
+        );
 
         let mut decoder = Decoder::new();
 
-        decoder.receive(&[01, 01, 00]);
-        decoder.receive(&[01, 01, 01, 00]);
-        decoder.receive(&[01, 02, 11, 01, 00]);
-        println!("before A: {:?}", decoder.get_buf());
+        // ペイロードとチェックサム
+        decoder.receive(&[1, 1, 0]);
+        decoder.receive(&[1, 1, 1, 0]);
+        decoder.receive(&[1, 3, 11, 11, 0]);
 
-        println!("{:?}", decoder.process_buffer().unwrap());
-        println!("{:?}", decoder.process_buffer().unwrap());
-        println!("{:?}", decoder.process_buffer().unwrap());
-        // decoder.process_buffer().unwrap();
+        assert_eq!(decoder.process_buffer(), Some(vec![]));
+        assert_eq!(decoder.process_buffer(), Some(vec![0]));
+        assert_eq!(decoder.process_buffer(), Some(vec![0, 11]));
 
-        println!("after A: {:?}", decoder.get_buf());
+        // フレームが分割されて届く
+        decoder.receive(&[1, 1, 0, 1]);
+        decoder.receive(&[1, 1, 0, 1]);
+        decoder.receive(&[3, 11, 11, 0]);
 
-        decoder.receive(&[01, 01, 00, 01]);
-        decoder.receive(&[01, 01, 00, 01]);
-        decoder.receive(&[02, 11, 01, 00]);
-
-        println!("before B: {:?}", decoder.get_buf());
-
-        println!("{:?}", decoder.process_buffer().unwrap());
-        println!("{:?}", decoder.process_buffer().unwrap());
-        println!("{:?}", decoder.process_buffer().unwrap());
-
-        println!("after B: {:?}", decoder.get_buf());
+        assert_eq!(decoder.process_buffer(), Some(vec![]));
+        assert_eq!(decoder.process_buffer(), Some(vec![0]));
+        assert_eq!(decoder.process_buffer(), Some(vec![0, 11]));
 
         assert_eq!(decoder.process_buffer(), None);
+
+        // 不正なフレームは取り除かれ、次のフレームは読める
+        decoder.receive(&[1, 2, 11, 1, 0, 1, 1, 0]);
+        assert_eq!(
+            decoder.next_frame(),
+            Some(Err(DecodeError::Checksum {
+                expected: 0,
+                actual: 11
+            }))
+        );
+        assert_eq!(decoder.next_frame(), Some(Ok(vec![])));
+        assert_eq!(decoder.next_frame(), None);
     }
 }
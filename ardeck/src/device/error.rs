@@ -0,0 +1,96 @@
+use std::{io, sync::Arc};
+
+/// デバイスの検出・セッション・通信・デコードのエラー
+///
+/// [`SessionEvent::Error`](crate::device::SessionEvent::Error) で通知されるため [`Clone`] を実装しています。
+/// 今後バリアントが増える可能性があるので、`match` では `_` の腕を用意してください。
+#[derive(Debug, Clone, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Device discovery error")]
+    Discovery(#[from] DiscoveryError),
+    #[error("Session error")]
+    Session(#[from] SessionError),
+    #[error("Transport error")]
+    Transport(#[from] TransportError),
+    #[error("Decode error")]
+    Decode(#[from] DecodeError),
+    /// ハンドラーがパニックした。値はパニックのメッセージ
+    #[error("Handler panicked: `{0}`")]
+    HandlerPanicked(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// デバイスの検出に関するエラー
+#[derive(Debug, Clone, thiserror::Error)]
+#[non_exhaustive]
+pub enum DiscoveryError {
+    /// シリアルポートの一覧を取得できなかった
+    #[error("Failed enumerate serial ports")]
+    Enumerate(#[source] serialport::Error),
+    /// デバイスIDに一致するデバイスが接続されていない
+    #[error("Device not found: `{device_id}`")]
+    NotFound { device_id: String },
+}
+
+/// セッションの状態に関するエラー
+#[derive(Debug, Clone, thiserror::Error)]
+#[non_exhaustive]
+pub enum SessionError {
+    /// セッションが開始されていない、または既に終了している
+    #[error("Session is not started")]
+    NotStarted,
+    /// 接続の試行回数が上限に達した。最後に失敗した原因を含む
+    #[error("Connect attempt limit reached after {attempts} attempts")]
+    AttemptLimitReached {
+        attempts: u16,
+        #[source]
+        source: TransportError,
+    },
+    /// デバイスIDとして使えない文字列
+    #[error("Invalid device id: `{0}`")]
+    InvalidDeviceId(String),
+}
+
+/// シリアルポートとの通信に関するエラー
+#[derive(Debug, Clone, thiserror::Error)]
+#[non_exhaustive]
+pub enum TransportError {
+    /// ポートを開けなかった
+    #[error("Failed open `{port_name}`")]
+    Open {
+        port_name: String,
+        #[source]
+        source: serialport::Error,
+    },
+    /// 読み込みに失敗した。この後セッションは再接続を試みる
+    #[error("Failed read from `{port_name}`")]
+    Read {
+        port_name: String,
+        #[source]
+        source: Arc<io::Error>,
+    },
+    /// 書き込みに失敗した
+    #[error("Failed write to `{port_name}`")]
+    Write {
+        port_name: String,
+        #[source]
+        source: Arc<io::Error>,
+    },
+}
+
+/// 受信したデータのデコードに関するエラー
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum DecodeError {
+    /// COBSとして不正なフレーム
+    #[error("Invalid COBS frame: {0:?}")]
+    Cobs(Vec<u8>),
+    /// チェックサムが一致しない
+    #[error("Checksum mismatch: expected {expected}, actual {actual}")]
+    Checksum { expected: u8, actual: u8 },
+    /// スイッチの情報としてパースできないペイロード
+    #[error("Invalid payload: {0:?}")]
+    Payload(Vec<u8>),
+}
@@ -0,0 +1,250 @@
+use regex::Regex;
+
+use crate::device::DeviceInfo;
+
+/// Arduino互換として知られているUSBデバイスの一覧
+///
+/// `(ベンダーID, プロダクトID, 名前)` の組で、プロダクトIDが `None` のものはベンダーの全製品が対象です。
+pub const KNOWN_ARDUINO_COMPATIBLE: &[(u16, Option<u16>, &str)] = &[
+    (0x2341, None, "Arduino"),
+    (0x2A03, None, "Arduino (arduino.org)"),
+    (0x1B4F, None, "SparkFun"),
+    (0x239A, None, "Adafruit"),
+    (0x2E8A, None, "Raspberry Pi RP2040"),
+    (0x1A86, Some(0x7523), "CH340"),
+    (0x1A86, Some(0x5523), "CH341"),
+    (0x1A86, Some(0x55D4), "CH9102"),
+    (0x0403, Some(0x6001), "FTDI FT232R"),
+    (0x10C4, Some(0xEA60), "Silicon Labs CP210x"),
+    (0x16C0, Some(0x0483), "Teensy"),
+];
+
+/// 既知のArduino互換デバイスであれば、その名前を返す
+pub fn known_device_name(vid: u16, pid: u16) -> Option<&'static str> {
+    KNOWN_ARDUINO_COMPATIBLE
+        .iter()
+        .find(|(v, p, _)| *v == vid && p.is_none_or(|p| p == pid))
+        .map(|(_, _, name)| *name)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FilterError {
+    #[error("Invalid regex: `{0}`")]
+    Regex(#[from] regex::Error),
+    #[error("Invalid glob pattern: `{0}`")]
+    Glob(#[from] glob::PatternError),
+}
+
+/// デバイス一覧から条件に合うデバイスを抽出する
+///
+/// 条件の種類ごとにはいずれか1つに一致すればよく、指定した全ての種類の条件を満たしたデバイスが抽出されます。
+/// 条件を1つも指定しなければ全てのデバイスが一致します。
+///
+/// # Example
+///
+/// ```
+/// use ardeck::device::{available_list, filter::DeviceFilter};
+///
+/// let filter = DeviceFilter::builder()
+///     .vid(0x2341)
+///     .vid_pid(0x1A86, 0x7523)
+///     .port_name("/dev/ttyACM*")
+///     .build()
+///     .unwrap();
+///
+/// let devices = filter.apply(available_list());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DeviceFilter {
+    ids: Vec<(u16, Option<u16>)>,
+    manufacturer: Vec<Regex>,
+    product: Vec<Regex>,
+    serial_number: Vec<String>,
+    port_name: Vec<glob::Pattern>,
+}
+
+impl DeviceFilter {
+    pub fn builder() -> DeviceFilterBuilder {
+        DeviceFilterBuilder::default()
+    }
+
+    /// [`KNOWN_ARDUINO_COMPATIBLE`] に含まれるデバイスに一致するフィルター
+    pub fn arduino_compatible() -> Self {
+        Self {
+            ids: KNOWN_ARDUINO_COMPATIBLE
+                .iter()
+                .map(|(vid, pid, _)| (*vid, *pid))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// デバイスが条件に一致するか判定する
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        let info = &device.usb_port_info;
+
+        let id = self.ids.is_empty()
+            || self
+                .ids
+                .iter()
+                .any(|(vid, pid)| *vid == info.vid && pid.is_none_or(|pid| pid == info.pid));
+
+        let manufacturer = self.manufacturer.is_empty()
+            || info
+                .manufacturer
+                .as_deref()
+                .is_some_and(|m| self.manufacturer.iter().any(|re| re.is_match(m)));
+
+        let product = self.product.is_empty()
+            || info
+                .product
+                .as_deref()
+                .is_some_and(|p| self.product.iter().any(|re| re.is_match(p)));
+
+        let serial_number = self.serial_number.is_empty()
+            || info
+                .serial_number
+                .as_ref()
+                .is_some_and(|s| self.serial_number.contains(s));
+
+        let port_name = self.port_name.is_empty()
+            || self
+                .port_name
+                .iter()
+                .any(|pattern| pattern.matches(&device.port_name));
+
+        id && manufacturer && product && serial_number && port_name
+    }
+
+    /// デバイス一覧のうち、条件に一致するデバイスだけを抽出する
+    pub fn apply(&self, devices: Vec<DeviceInfo>) -> Vec<DeviceInfo> {
+        devices
+            .into_iter()
+            .filter(|device| self.matches(device))
+            .collect()
+    }
+}
+
+/// [`DeviceFilter`] を作成する
+#[derive(Debug, Clone, Default)]
+pub struct DeviceFilterBuilder {
+    ids: Vec<(u16, Option<u16>)>,
+    manufacturer: Vec<String>,
+    product: Vec<String>,
+    serial_number: Vec<String>,
+    port_name: Vec<String>,
+}
+
+impl DeviceFilterBuilder {
+    /// ベンダーIDに一致するデバイス
+    pub fn vid(mut self, vid: u16) -> Self {
+        self.ids.push((vid, None));
+        self
+    }
+
+    /// ベンダーIDとプロダクトIDの組に一致するデバイス
+    pub fn vid_pid(mut self, vid: u16, pid: u16) -> Self {
+        self.ids.push((vid, Some(pid)));
+        self
+    }
+
+    /// [`KNOWN_ARDUINO_COMPATIBLE`] に含まれるデバイス
+    pub fn arduino_compatible(mut self) -> Self {
+        self.ids.extend(
+            KNOWN_ARDUINO_COMPATIBLE
+                .iter()
+                .map(|(vid, pid, _)| (*vid, *pid)),
+        );
+        self
+    }
+
+    /// 製造元の文字列が正規表現に一致するデバイス
+    pub fn manufacturer(mut self, regex: impl Into<String>) -> Self {
+        self.manufacturer.push(regex.into());
+        self
+    }
+
+    /// 製品名の文字列が正規表現に一致するデバイス
+    pub fn product(mut self, regex: impl Into<String>) -> Self {
+        self.product.push(regex.into());
+        self
+    }
+
+    /// シリアル番号が一致するデバイス
+    pub fn serial_number(mut self, serial_number: impl Into<String>) -> Self {
+        self.serial_number.push(serial_number.into());
+        self
+    }
+
+    /// ポート名がglobパターンに一致するデバイス ex: `/dev/ttyUSB*`, `COM?`
+    pub fn port_name(mut self, pattern: impl Into<String>) -> Self {
+        self.port_name.push(pattern.into());
+        self
+    }
+
+    pub fn build(self) -> Result<DeviceFilter, FilterError> {
+        Ok(DeviceFilter {
+            ids: self.ids,
+            manufacturer: self
+                .manufacturer
+                .iter()
+                .map(|re| Regex::new(re))
+                .collect::<Result<_, _>>()?,
+            product: self
+                .product
+                .iter()
+                .map(|re| Regex::new(re))
+                .collect::<Result<_, _>>()?,
+            serial_number: self.serial_number,
+            port_name: self
+                .port_name
+                .iter()
+                .map(|pattern| glob::Pattern::new(pattern))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::test_device::usb_device;
+
+    #[test]
+    fn filter() {
+        let uno = usb_device("/dev/ttyACM0", 0x2341, 0x0043, Some("Arduino LLC"));
+        let ch340 = usb_device("/dev/ttyUSB0", 0x1A86, 0x7523, None);
+        let ch341 = usb_device("/dev/ttyUSB1", 0x1A86, 0x5512, None);
+        let mouse = usb_device("/dev/ttyS0", 0x046D, 0xC077, Some("Logitech"));
+
+        let arduino = DeviceFilter::arduino_compatible();
+        assert!(arduino.matches(&uno));
+        assert!(arduino.matches(&ch340));
+        assert!(!arduino.matches(&ch341));
+        assert!(!arduino.matches(&mouse));
+
+        let filter = DeviceFilter::builder()
+            .vid(0x1A86)
+            .port_name("/dev/ttyUSB*")
+            .build()
+            .unwrap();
+        assert_eq!(
+            filter.apply(vec![
+                uno.clone(),
+                ch340.clone(),
+                ch341.clone(),
+                mouse.clone()
+            ]),
+            vec![ch340, ch341]
+        );
+
+        let filter = DeviceFilter::builder()
+            .manufacturer("(?i)^arduino")
+            .build()
+            .unwrap();
+        assert!(filter.matches(&uno));
+        assert!(!filter.matches(&mouse));
+
+        assert!(DeviceFilter::builder().product("(").build().is_err());
+    }
+}
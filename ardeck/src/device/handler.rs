@@ -0,0 +1,422 @@
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use smol::{channel::Sender, lock::Mutex};
+
+use crate::device::{ArdeckConnectionHandler, Error, SessionEvent, switch::SwitchInfo};
+
+/// ハンドラーの優先度の既定値
+pub const DEFAULT_PRIORITY: i32 = 0;
+
+/// ハンドラー1回の実行にかけてよい時間の既定値
+pub const DEFAULT_TIME_BUDGET: Duration = Duration::from_millis(50);
+
+/// 登録したハンドラーを識別するID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HandlerId(u64);
+
+impl HandlerId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// ハンドラーの登録を解除するためのガード
+///
+/// ドロップすると登録が解除されます。セッションが続く限り登録したままにする場合は [`HandlerGuard::detach`] を使用します。
+#[must_use = "ドロップするとハンドラーの登録が解除されます"]
+#[derive(Debug)]
+pub struct HandlerGuard {
+    id: HandlerId,
+    alive: Option<Arc<AtomicBool>>,
+}
+
+impl HandlerGuard {
+    pub fn id(&self) -> HandlerId {
+        self.id
+    }
+
+    /// ガードを手放し、ハンドラーを登録したままにする
+    ///
+    /// 返されたIDで後から [`Session::remove_handler`](crate::device::Session::remove_handler) を呼べます。
+    pub fn detach(mut self) -> HandlerId {
+        self.alive = None;
+        self.id
+    }
+}
+
+impl Drop for HandlerGuard {
+    fn drop(&mut self) {
+        // ハンドラーの実行中にドロップされることもあるので、ロックは取らずに印だけ付ける
+        if let Some(alive) = self.alive.take() {
+            alive.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+struct Entry {
+    id: HandlerId,
+    priority: i32,
+    alive: Arc<AtomicBool>,
+    handler: ArdeckConnectionHandler,
+}
+
+/// 優先度順に並んだハンドラーの一覧
+///
+/// 一覧は変更のたびに作り直すので、実行中の [`Snapshot`] には影響しません。
+#[derive(Default)]
+pub(super) struct HandlerList {
+    entries: Arc<[Arc<Entry>]>,
+}
+
+/// ある時点のハンドラーの一覧
+///
+/// ロックを取らずに実行できるので、実行中のハンドラーからハンドラーを追加・解除できます。
+pub(super) struct Snapshot(Arc<[Arc<Entry>]>);
+
+impl HandlerList {
+    /// ハンドラーを追加する。同じ優先度の中では追加した順に実行されます
+    pub(super) fn insert(
+        &mut self,
+        handler: ArdeckConnectionHandler,
+        priority: i32,
+    ) -> HandlerGuard {
+        let id = HandlerId::next();
+        let alive = Arc::new(AtomicBool::new(true));
+
+        let mut entries = self.entries.to_vec();
+        let index = entries
+            .iter()
+            .position(|entry| entry.priority < priority)
+            .unwrap_or(entries.len());
+        entries.insert(
+            index,
+            Arc::new(Entry {
+                id,
+                priority,
+                alive: alive.clone(),
+                handler,
+            }),
+        );
+        self.entries = entries.into();
+
+        HandlerGuard {
+            id,
+            alive: Some(alive),
+        }
+    }
+
+    pub(super) fn remove(&mut self, id: HandlerId) -> bool {
+        let len = self.entries.len();
+        self.retain(|entry| entry.id != id);
+        self.entries.len() != len
+    }
+
+    /// 現在のハンドラーの一覧。ガードがドロップされたハンドラーはここで取り除く
+    pub(super) fn snapshot(&mut self) -> Snapshot {
+        if self
+            .entries
+            .iter()
+            .any(|entry| !entry.alive.load(Ordering::Relaxed))
+        {
+            self.retain(|entry| entry.alive.load(Ordering::Relaxed));
+        }
+
+        Snapshot(self.entries.clone())
+    }
+
+    fn retain(&mut self, f: impl Fn(&Entry) -> bool) {
+        self.entries = self
+            .entries
+            .iter()
+            .filter(|entry| f(entry))
+            .cloned()
+            .collect();
+    }
+}
+
+impl Snapshot {
+    /// 優先度の高い順にハンドラーを実行する
+    ///
+    /// パニックしたハンドラーがあっても残りのハンドラーは実行され、パニックのメッセージを返します。
+    pub(super) fn dispatch(
+        &self,
+        event: &SessionEvent,
+        budget: Duration,
+        stats: &HandlerStats,
+    ) -> Vec<String> {
+        let mut panicked = Vec::new();
+        for entry in self.0.iter() {
+            // 実行中に解除されたハンドラーは呼ばない
+            if !entry.alive.load(Ordering::Relaxed) {
+                continue;
+            }
+
+            let started = Instant::now();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| (entry.handler)(event.clone())));
+            let elapsed = started.elapsed();
+
+            if elapsed > budget {
+                stats.slow.fetch_add(1, Ordering::Relaxed);
+                log::warn!(
+                    "Handler {:?} took {:?} (budget {:?})",
+                    entry.id,
+                    elapsed,
+                    budget
+                );
+            }
+
+            if let Err(payload) = result {
+                stats.panicked.fetch_add(1, Ordering::Relaxed);
+                let message = panic_message(payload.as_ref());
+                log::error!("Handler {:?} panicked: {}", entry.id, message);
+                panicked.push(message);
+            }
+        }
+
+        panicked
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// ハンドラーの実行状況
+#[derive(Debug, Default)]
+pub struct HandlerStats {
+    slow: AtomicU64,
+    panicked: AtomicU64,
+}
+
+impl HandlerStats {
+    /// 実行時間が時間の予算を超えた回数
+    pub fn slow_calls(&self) -> u64 {
+        self.slow.load(Ordering::Relaxed)
+    }
+
+    /// ハンドラーがパニックした回数
+    pub fn panics(&self) -> u64 {
+        self.panicked.load(Ordering::Relaxed)
+    }
+}
+
+/// イベントをキューに積み、専用のスレッドでハンドラーを実行する
+///
+/// ハンドラーが遅くてもシリアルポートの読み込みは止まりません。
+/// 受信したデータを捨てないようにキューには上限を設けていないので、
+/// ハンドラーが受信に追いつかない状態が続くとキューは増え続けます。
+/// [`HandlerStats::slow_calls`] で遅いハンドラーを検出してください。
+///
+/// ハンドラーは一覧のロックを取らずに実行するので、ハンドラーの中からハンドラーを追加・解除できます。
+pub(super) struct Dispatcher {
+    tx: Sender<SessionEvent>,
+    thread: JoinHandle<()>,
+}
+
+impl Dispatcher {
+    pub(super) fn spawn(
+        handler: Arc<Mutex<HandlerList>>,
+        budget: Duration,
+        stats: Arc<HandlerStats>,
+    ) -> Self {
+        let (tx, rx) = smol::channel::unbounded::<SessionEvent>();
+
+        let thread = thread::spawn(move || {
+            while let Ok(event) = rx.recv_blocking() {
+                let snapshot = handler.lock_blocking().snapshot();
+                let panicked = snapshot.dispatch(&event, budget, &stats);
+
+                // パニックを通知するイベントでさらにパニックしても、ログに残すだけにする
+                for message in panicked {
+                    let event = SessionEvent::Error(Error::HandlerPanicked(message));
+                    snapshot.dispatch(&event, budget, &stats);
+                }
+            }
+        });
+
+        Self { tx, thread }
+    }
+
+    /// イベントをキューに積む
+    pub(super) fn emit(&self, event: SessionEvent) {
+        if self.tx.try_send(event).is_err() {
+            log::error!("Dispatcher already exited");
+        }
+    }
+
+    /// キューに積まれたイベントを全て実行してから終了する
+    pub(super) async fn close(self) {
+        let Self { tx, thread } = self;
+        drop(tx);
+
+        if smol::unblock(move || thread.join()).await.is_err() {
+            log::error!("Dispatcher thread panicked");
+        }
+    }
+}
+
+/// 条件に一致するイベントだけを受け取るハンドラーを作成する
+pub fn filter(
+    predicate: impl Fn(&SessionEvent) -> bool + Send + Sync + 'static,
+    handler: impl Fn(SessionEvent) + Send + Sync + 'static,
+) -> ArdeckConnectionHandler {
+    Box::new(move |event| {
+        if predicate(&event) {
+            handler(event);
+        }
+    })
+}
+
+/// 指定したピンのスイッチのデータだけを受け取るハンドラーを作成する
+pub fn data_for_pin(
+    pin: u8,
+    handler: impl Fn(SwitchInfo) + Send + Sync + 'static,
+) -> ArdeckConnectionHandler {
+    Box::new(move |event| {
+        if let SessionEvent::Data(info) = event
+            && info.pin == pin
+        {
+            handler(info);
+        }
+    })
+}
+
+/// 接続状況の変化だけを受け取るハンドラーを作成する
+///
+/// 対象のイベントは [`SessionEvent::is_connection_state`] を参照してください。
+pub fn connection_state(
+    handler: impl Fn(SessionEvent) + Send + Sync + 'static,
+) -> ArdeckConnectionHandler {
+    filter(SessionEvent::is_connection_state, handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    fn recorder(
+        log: &Arc<Mutex<Vec<&'static str>>>,
+        name: &'static str,
+    ) -> ArdeckConnectionHandler {
+        let log = log.clone();
+        Box::new(move |_| log.lock().unwrap().push(name))
+    }
+
+    #[test]
+    fn priority_and_removal() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut list = HandlerList::default();
+
+        let low = list.insert(recorder(&log, "low"), -1);
+        let first = list.insert(recorder(&log, "first"), DEFAULT_PRIORITY);
+        let high = list.insert(recorder(&log, "high"), 10).detach();
+        let _second = list.insert(recorder(&log, "second"), DEFAULT_PRIORITY);
+
+        let stats = HandlerStats::default();
+        list.snapshot()
+            .dispatch(&SessionEvent::Connected, DEFAULT_TIME_BUDGET, &stats);
+        assert_eq!(*log.lock().unwrap(), ["high", "first", "second", "low"]);
+
+        log.lock().unwrap().clear();
+        drop(low);
+        drop(first);
+        assert!(list.remove(high));
+        assert!(!list.remove(high));
+
+        list.snapshot()
+            .dispatch(&SessionEvent::Connected, DEFAULT_TIME_BUDGET, &stats);
+        assert_eq!(*log.lock().unwrap(), ["second"]);
+    }
+
+    #[test]
+    fn isolate_panic_and_slow() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut list = HandlerList::default();
+        let stats = HandlerStats::default();
+
+        let _panic = list.insert(Box::new(|_| panic!("boom")), 1);
+        let _after = list.insert(recorder(&log, "after"), DEFAULT_PRIORITY);
+
+        let panicked =
+            list.snapshot()
+                .dispatch(&SessionEvent::Connected, DEFAULT_TIME_BUDGET, &stats);
+        assert_eq!(panicked, ["boom"]);
+        assert_eq!(*log.lock().unwrap(), ["after"]);
+        assert_eq!(stats.panics(), 1);
+
+        let mut list = HandlerList::default();
+        let stats = HandlerStats::default();
+        let _slow = list.insert(
+            Box::new(|_| thread::sleep(Duration::from_millis(20))),
+            DEFAULT_PRIORITY,
+        );
+
+        list.snapshot()
+            .dispatch(&SessionEvent::Connected, Duration::from_millis(5), &stats);
+        assert_eq!(stats.slow_calls(), 1);
+        assert_eq!(stats.panics(), 0);
+    }
+
+    #[test]
+    fn modify_from_handler() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let list = Arc::new(smol::lock::Mutex::new(HandlerList::default()));
+
+        // ハンドラーの中からハンドラーを追加してもデッドロックしない
+        let l = list.clone();
+        let added = log.clone();
+        list.lock_blocking()
+            .insert(
+                Box::new(move |_| {
+                    l.lock_blocking()
+                        .insert(recorder(&added, "added"), -1)
+                        .detach();
+                }),
+                DEFAULT_PRIORITY,
+            )
+            .detach();
+
+        let stats = Arc::new(HandlerStats::default());
+        let dispatcher = Dispatcher::spawn(list.clone(), DEFAULT_TIME_BUDGET, stats);
+        dispatcher.emit(SessionEvent::Connected);
+        dispatcher.emit(SessionEvent::Connected);
+        smol::block_on(dispatcher.close());
+
+        // 追加されたハンドラーは次のイベントから実行される
+        assert_eq!(*log.lock().unwrap(), ["added"]);
+    }
+
+    #[test]
+    fn filtered() {
+        let pins = Arc::new(Mutex::new(Vec::new()));
+        let p = pins.clone();
+        let handler = data_for_pin(3, move |info| p.lock().unwrap().push(info.pin));
+
+        for pin in [1, 3, 5, 3] {
+            handler(SessionEvent::Data(SwitchInfo {
+                pin,
+                ..Default::default()
+            }));
+        }
+        handler(SessionEvent::Connected);
+        assert_eq!(*pins.lock().unwrap(), [3, 3]);
+    }
+}
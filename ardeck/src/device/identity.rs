@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::device::DeviceInfo;
+
+/// デバイスから送られる識別情報のペイロードの先頭バイト
+///
+/// `[0xFF, 種類, IDのバイト列...]` の形式で、種類は `0` がハードウェア固有ID、`1` がEEPROMに書き込まれたIDです。
+pub const IDENTITY_REPORT: u8 = 0xFF;
+
+/// デバイスのEEPROMへIDを書き込むコマンドの先頭バイト
+///
+/// `[0xFE, 長さ, IDのバイト列..., チェックサム]` の形式で送信します。
+pub const ASSIGN_COMMAND: u8 = 0xFE;
+
+/// 書き込めるIDの最大長
+pub const MAX_ID_LEN: usize = 32;
+
+/// デバイスIDの取得元
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum IdentitySource {
+    /// ハンドシェイクでデバイスが報告したハードウェア固有ID
+    Hardware,
+    /// ホストが割り当ててデバイスのEEPROMに書き込んだID
+    Assigned,
+    /// USBのベンダーID・プロダクトID・シリアル番号から生成したID
+    Usb,
+}
+
+/// デバイスを識別するためのID
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceIdentity {
+    /// デバイスID
+    pub id: String,
+    /// IDの取得元
+    pub source: IdentitySource,
+}
+
+impl DeviceIdentity {
+    /// USBのポート情報から生成されたIDを使用する
+    pub fn usb(device_info: &DeviceInfo) -> Self {
+        Self {
+            id: device_info.device_id.clone(),
+            source: IdentitySource::Usb,
+        }
+    }
+}
+
+/// デバイスが報告した識別情報をパースする。識別情報でなければ `None` を返します。
+///
+/// IDが空の場合、デバイスはIDを持っていないとみなします。
+pub fn parse_report(payload: &[u8]) -> Option<DeviceIdentity> {
+    let (&IDENTITY_REPORT, rest) = payload.split_first()? else {
+        return None;
+    };
+
+    let (kind, id) = rest.split_first()?;
+    let source = match kind {
+        0 => IdentitySource::Hardware,
+        1 => IdentitySource::Assigned,
+        _ => return None,
+    };
+
+    if id.is_empty() {
+        return None;
+    }
+
+    Some(DeviceIdentity {
+        id: String::from_utf8_lossy(id).into_owned(),
+        source,
+    })
+}
+
+/// IDとして使える文字列か判定する
+///
+/// 1文字以上 [`MAX_ID_LEN`] 文字以下の、英数字と `-`, `_` からなる文字列が使えます。
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// IDをデバイスのEEPROMへ書き込むコマンドを作成する。IDが使えない文字列なら `None` を返します。
+pub fn assign_command(id: &str) -> Option<Vec<u8>> {
+    if !is_valid_id(id) {
+        return None;
+    }
+
+    let mut command = vec![ASSIGN_COMMAND, id.len() as u8];
+    command.extend_from_slice(id.as_bytes());
+
+    let sum = command.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    command.push(sum);
+
+    Some(command)
+}
+
+/// ホストから割り当てるための新しいIDを生成する
+pub fn generate_id() -> String {
+    let nanos = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64;
+    format!("ARD-{:016X}", nanos ^ ((std::process::id() as u64) << 32))
+}
+
+/// 同じデバイスIDを持つデバイスの組
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collision {
+    /// 重複しているデバイスID
+    pub device_id: String,
+    /// 重複しているデバイスのポート名
+    pub port_names: Vec<String>,
+}
+
+/// デバイス一覧からデバイスIDの重複を検出する
+///
+/// シリアル番号を持たない同じ型番のデバイスを複数接続すると重複します。
+/// その場合はデバイスにIDを割り当ててください。
+pub fn find_collisions(devices: &[DeviceInfo]) -> Vec<Collision> {
+    let mut ports: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for device in devices {
+        ports
+            .entry(device.device_id.as_str())
+            .or_default()
+            .push(device.port_name.clone());
+    }
+
+    ports
+        .into_iter()
+        .filter(|(_, port_names)| port_names.len() > 1)
+        .map(|(device_id, port_names)| Collision {
+            device_id: device_id.to_string(),
+            port_names,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        assert_eq!(
+            parse_report(&[IDENTITY_REPORT, 1, b'a', b'b']),
+            Some(DeviceIdentity {
+                id: "ab".into(),
+                source: IdentitySource::Assigned,
+            })
+        );
+        assert_eq!(parse_report(&[IDENTITY_REPORT, 0]), None);
+        assert_eq!(parse_report(&[0b00000011]), None);
+    }
+
+    #[test]
+    fn command() {
+        assert_eq!(
+            assign_command("ab"),
+            Some(vec![
+                ASSIGN_COMMAND,
+                2,
+                b'a',
+                b'b',
+                ASSIGN_COMMAND.wrapping_add(2 + b'a' + b'b')
+            ])
+        );
+        assert_eq!(assign_command(""), None);
+        assert_eq!(assign_command("a b"), None);
+        assert!(is_valid_id(&generate_id()));
+    }
+}
@@ -0,0 +1,426 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex as StdMutex, PoisonError},
+};
+
+use smol::{
+    channel::{Receiver, Sender},
+    lock::Mutex,
+};
+
+use crate::device::{
+    DeviceInfo, Session, SessionBuilder, SessionEvent,
+    watcher::{DeviceEvent, DeviceWatcher, DeviceWatcherBuilder},
+};
+
+/// 受け取られていないイベントを保持する最大数
+///
+/// [`SessionManager::events`] から受け取られないまま上限に達すると、新しいイベントは捨てられます。
+pub const EVENT_CAPACITY: usize = 1024;
+
+type SessionFactory = dyn Fn(DeviceInfo) -> SessionBuilder + Send + Sync + 'static;
+
+/// どのデバイスのセッションから発行されたかを含む [`SessionEvent`]
+#[derive(Debug, Clone)]
+pub struct ManagerEvent {
+    /// イベントを発行したセッションのキー
+    ///
+    /// 通常はデバイスIDです。詳しくは [`SessionManager`] を参照してください。
+    pub device_id: String,
+    /// セッションのイベント
+    pub event: SessionEvent,
+}
+
+/// 複数のデバイスのセッションをまとめて管理する
+///
+/// セッションはポートごとに1つ作成され、デバイスIDをキーとして管理されます。
+/// シリアル番号を持たない同じ型番のデバイスのようにデバイスIDが重複した場合は、
+/// 後から追加されたデバイスを `デバイスID@ポート名` のキーで管理します。
+/// デバイスがIDを報告すると ([`SessionEvent::Identified`])、キーは報告されたIDに変わります。
+///
+/// 全てのセッションのイベントは [`SessionManager::events`] から受け取れます。
+/// 受け取られていないイベントは [`EVENT_CAPACITY`] 件まで保持されます。
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use ardeck::device::{
+///     SessionBuilder, filter::DeviceFilter, manager::SessionManager,
+///     watcher::DeviceWatcherBuilder,
+/// };
+///
+/// smol::block_on(async {
+///     let mut manager = SessionManager::with_factory(|device_info| {
+///         SessionBuilder::new(device_info).connect_retry_interval(Duration::from_secs(1))
+///     });
+///     manager.watch(DeviceWatcherBuilder::new().filter(DeviceFilter::arduino_compatible()));
+///
+///     let events = manager.events();
+///     while let Ok(e) = events.recv().await {
+///         println!("{}: {:?}", e.device_id, e.event);
+///     }
+/// });
+/// ```
+pub struct SessionManager {
+    shared: Arc<Shared>,
+    event_rx: Receiver<ManagerEvent>,
+    /// デバイスが報告したIDでキーを変更するタスク
+    _rekey: smol::Task<()>,
+    /// ホットプラグの監視
+    watcher: Option<(DeviceWatcher, smol::Task<()>)>,
+}
+
+/// 管理しているセッション
+struct Managed {
+    session: Session,
+    /// 現在のキー。イベントの送信時に参照する
+    key: Arc<StdMutex<String>>,
+}
+
+/// キーの変更の依頼。セッションの現在のキーと、デバイスが報告したID
+type Rekey = (Arc<StdMutex<String>>, String);
+
+/// セッションとイベントの送信先。監視のタスクと共有する
+struct Shared {
+    sessions: Mutex<HashMap<String, Managed>>,
+    factory: Box<SessionFactory>,
+    event_tx: Sender<ManagerEvent>,
+    rekey_tx: Sender<Rekey>,
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::with_factory(SessionBuilder::new)
+    }
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// セッションを作成するときの設定を指定して作成する
+    pub fn with_factory(
+        factory: impl Fn(DeviceInfo) -> SessionBuilder + Send + Sync + 'static,
+    ) -> Self {
+        let (event_tx, event_rx) = smol::channel::bounded(EVENT_CAPACITY);
+        let (rekey_tx, rekey_rx) = smol::channel::unbounded::<Rekey>();
+
+        let shared = Arc::new(Shared {
+            sessions: Mutex::new(HashMap::new()),
+            factory: Box::new(factory),
+            event_tx,
+            rekey_tx,
+        });
+
+        let weak = Arc::downgrade(&shared);
+        let rekey = smol::spawn(async move {
+            while let Ok((key, id)) = rekey_rx.recv().await {
+                let Some(shared) = weak.upgrade() else {
+                    break;
+                };
+                shared.rekey(&key, id).await;
+            }
+        });
+
+        Self {
+            shared,
+            event_rx,
+            _rekey: rekey,
+            watcher: None,
+        }
+    }
+
+    /// 全てのセッションのイベントを受け取る
+    ///
+    /// 受け取る側が追いつかず [`EVENT_CAPACITY`] 件たまると、それ以降のイベントは捨てられます。
+    pub fn events(&self) -> Receiver<ManagerEvent> {
+        self.event_rx.clone()
+    }
+
+    /// 管理しているセッションのキー一覧
+    pub async fn device_ids(&self) -> Vec<String> {
+        self.shared.sessions.lock().await.keys().cloned().collect()
+    }
+
+    /// デバイスのセッションを作成して開始する。既に同じポートのセッションがあれば何もしない
+    pub async fn add(&self, device_info: DeviceInfo) {
+        self.shared.add(device_info).await;
+    }
+
+    /// キーに対応するセッションを終了して管理から外す
+    pub async fn remove(&self, device_id: &str) {
+        self.shared.remove(device_id).await;
+    }
+
+    /// ホットプラグのイベントに応じてセッションを開始・終了する
+    pub async fn handle_device_event(&self, event: DeviceEvent) {
+        self.shared.handle_device_event(event).await;
+    }
+
+    /// デバイスの監視を開始し、接続・取り外しに合わせてセッションを開始・終了する
+    pub fn watch(&mut self, builder: DeviceWatcherBuilder) {
+        let (tx, rx) = smol::channel::unbounded();
+
+        let mut watcher = builder
+            .handler(Box::new(move |event| {
+                let _ = tx.try_send(event);
+            }))
+            .build();
+        watcher.start();
+
+        let shared = self.shared.clone();
+        let task = smol::spawn(async move {
+            while let Ok(event) = rx.recv().await {
+                shared.handle_device_event(event).await;
+            }
+        });
+
+        self.watcher = Some((watcher, task));
+    }
+
+    /// デバイスの監視と全てのセッションを終了する
+    ///
+    /// 全てのセッションのシリアルポートが解放されるまで待ちます。
+    pub async fn shutdown(&mut self) {
+        self.watcher = None;
+
+        let sessions: Vec<Session> = self
+            .shared
+            .sessions
+            .lock()
+            .await
+            .drain()
+            .map(|(_, managed)| managed.session)
+            .collect();
+
+        log::info!("Shutdown {} sessions", sessions.len());
+        for mut session in sessions {
+            session.stop().await;
+        }
+    }
+}
+
+impl Shared {
+    async fn add(&self, device_info: DeviceInfo) {
+        let mut sessions = self.sessions.lock().await;
+        if sessions
+            .values()
+            .any(|managed| managed.session.device_info().port_name == device_info.port_name)
+        {
+            return;
+        }
+
+        let key = match sessions.get(&device_info.device_id) {
+            Some(managed) => {
+                log::warn!(
+                    "Device id `{}` collides between {} and {}. Assign an id to the device.",
+                    device_info.device_id,
+                    managed.session.device_info().port_name,
+                    device_info.port_name
+                );
+                format!("{}@{}", device_info.device_id, device_info.port_name)
+            }
+            None => device_info.device_id.clone(),
+        };
+
+        let current = Arc::new(StdMutex::new(key.clone()));
+        let event_tx = self.event_tx.clone();
+        let rekey_tx = self.rekey_tx.clone();
+        let handler_key = current.clone();
+
+        let mut session = (self.factory)(device_info)
+            .handler(Box::new(move |event| {
+                if let SessionEvent::Identified(identity) = &event {
+                    let _ = rekey_tx.try_send((handler_key.clone(), identity.id.clone()));
+                }
+
+                let event = ManagerEvent {
+                    device_id: handler_key
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .clone(),
+                    event,
+                };
+                if let Err(smol::channel::TrySendError::Full(event)) = event_tx.try_send(event) {
+                    log::trace!("Event queue is full, dropped: {:?}", event);
+                }
+            }))
+            .build();
+        session.start();
+
+        sessions.insert(
+            key,
+            Managed {
+                session,
+                key: current,
+            },
+        );
+    }
+
+    async fn remove(&self, key: &str) {
+        let managed = self.sessions.lock().await.remove(key);
+        if let Some(mut managed) = managed {
+            managed.session.stop().await;
+            log::info!("Session removed: {}", key);
+        }
+    }
+
+    async fn handle_device_event(&self, event: DeviceEvent) {
+        match event {
+            DeviceEvent::Added(device_info) => self.add(device_info).await,
+            DeviceEvent::Removed(device_info) => {
+                // 同じデバイスIDのデバイスがあるので、ポート名で探す
+                let key = self
+                    .sessions
+                    .lock()
+                    .await
+                    .iter()
+                    .find(|(_, managed)| {
+                        managed.session.device_info().port_name == device_info.port_name
+                    })
+                    .map(|(key, _)| key.clone());
+
+                if let Some(key) = key {
+                    self.remove(&key).await;
+                }
+            }
+        }
+    }
+
+    /// セッションのキーをデバイスが報告したIDに変更する
+    ///
+    /// 報告されたIDが既に他のセッションのキーになっている場合は変更しません。
+    async fn rekey(&self, key: &StdMutex<String>, id: String) {
+        let mut sessions = self.sessions.lock().await;
+        let current = key.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        if current == id {
+            return;
+        }
+        if sessions.contains_key(&id) {
+            log::warn!(
+                "Reported id `{}` collides with another device, keep `{}`",
+                id,
+                current
+            );
+            return;
+        }
+
+        let Some(managed) = sessions.remove(&current) else {
+            return;
+        };
+        log::info!("Session identified: {} -> {}", current, id);
+
+        *managed.key.lock().unwrap_or_else(PoisonError::into_inner) = id.clone();
+        sessions.insert(id, managed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::device::test_device::device;
+
+    /// 存在しないポートへの接続を短い間隔で再試行し続けるマネージャー
+    fn manager() -> SessionManager {
+        SessionManager::with_factory(|device_info| {
+            SessionBuilder::new(device_info).connect_retry_interval(Duration::from_millis(10))
+        })
+    }
+
+    async fn sorted_ids(manager: &SessionManager) -> Vec<String> {
+        let mut ids = manager.device_ids().await;
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn add_and_remove() {
+        smol::block_on(async {
+            let mut manager = manager();
+            let a = device("/dev/ardeck-test-a", "A");
+            let b = device("/dev/ardeck-test-b", "B");
+
+            manager.add(a.clone()).await;
+            manager.add(a.clone()).await;
+            manager.add(b.clone()).await;
+            assert_eq!(sorted_ids(&manager).await, ["A", "B"]);
+
+            manager.remove("A").await;
+            assert_eq!(sorted_ids(&manager).await, ["B"]);
+            // 管理していないデバイスは無視される
+            manager.remove("A").await;
+
+            manager.shutdown().await;
+            assert!(manager.device_ids().await.is_empty());
+        });
+    }
+
+    #[test]
+    fn device_events() {
+        smol::block_on(async {
+            let manager = manager();
+            let events = manager.events();
+            let a = device("/dev/ardeck-test-a", "A");
+
+            manager
+                .handle_device_event(DeviceEvent::Added(a.clone()))
+                .await;
+            assert_eq!(sorted_ids(&manager).await, ["A"]);
+
+            // セッションのイベントはデバイスIDと一緒に届く
+            let event = events.recv().await.unwrap();
+            assert_eq!(event.device_id, "A");
+            assert!(matches!(event.event, SessionEvent::Connecting));
+
+            manager
+                .handle_device_event(DeviceEvent::Removed(a.clone()))
+                .await;
+            assert!(manager.device_ids().await.is_empty());
+        });
+    }
+
+    #[test]
+    fn same_device_id() {
+        smol::block_on(async {
+            let mut manager = manager();
+            let a = device("/dev/ardeck-test-a", "2341-0043");
+            let b = device("/dev/ardeck-test-b", "2341-0043");
+
+            manager.add(a.clone()).await;
+            manager.add(b.clone()).await;
+            assert_eq!(
+                sorted_ids(&manager).await,
+                ["2341-0043", "2341-0043@/dev/ardeck-test-b"]
+            );
+
+            // 報告されたIDでキーが変わる
+            let key = manager
+                .shared
+                .sessions
+                .lock()
+                .await
+                .get("2341-0043@/dev/ardeck-test-b")
+                .map(|managed| managed.key.clone())
+                .unwrap();
+            manager.shared.rekey(&key, "ARD-B".into()).await;
+            assert_eq!(sorted_ids(&manager).await, ["2341-0043", "ARD-B"]);
+            assert_eq!(*key.lock().unwrap(), "ARD-B");
+
+            // 他のセッションのキーと重なるIDには変えない
+            manager.shared.rekey(&key, "2341-0043".into()).await;
+            assert_eq!(sorted_ids(&manager).await, ["2341-0043", "ARD-B"]);
+
+            // 取り外しはポート名で判別する
+            manager
+                .handle_device_event(DeviceEvent::Removed(b.clone()))
+                .await;
+            assert_eq!(sorted_ids(&manager).await, ["2341-0043"]);
+
+            manager.shutdown().await;
+        });
+    }
+}
@@ -1,21 +1,16 @@
 use serde::{Deserialize, Serialize};
 
 /// Arduinoに接続されているスイッチの種類を示す列挙型
-#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, Default, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum SwitchKind {
     /// デジタルスイッチ ex: タクトスイッチ, トグルスイッチ
+    #[default]
     Digital = 0,
     /// アナログスイッチ ex: ポテンションメーター, アナログジョイスティック
     Analog = 1,
 }
 
-impl Default for SwitchKind {
-    fn default() -> Self {
-        SwitchKind::Digital
-    }
-}
-
 /// デバイスによって押されたスイッチの情報を保持する構造体
 #[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
 #[serde(rename_all = "camelCase")]
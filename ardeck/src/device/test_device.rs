@@ -0,0 +1,28 @@
+//! テスト用のデバイス情報
+
+use serialport::UsbPortInfo;
+
+use crate::device::DeviceInfo;
+
+/// USBのIDとメーカー名を指定したデバイス。デバイスIDは空になります
+pub fn usb_device(port_name: &str, vid: u16, pid: u16, manufacturer: Option<&str>) -> DeviceInfo {
+    DeviceInfo {
+        port_name: port_name.into(),
+        usb_port_info: UsbPortInfo {
+            vid,
+            pid,
+            serial_number: None,
+            manufacturer: manufacturer.map(Into::into),
+            product: None,
+        },
+        device_id: String::new(),
+    }
+}
+
+/// デバイスIDを指定したArduino Uno
+pub fn device(port_name: &str, device_id: &str) -> DeviceInfo {
+    DeviceInfo {
+        device_id: device_id.into(),
+        ..usb_device(port_name, 0x2341, 0x0043, None)
+    }
+}
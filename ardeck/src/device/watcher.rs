@@ -0,0 +1,268 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    sync::Arc,
+    time::Duration,
+};
+
+use smol::lock::Mutex;
+
+use crate::{
+    device::{DeviceInfo, available_list, filter::DeviceFilter},
+    poll,
+};
+
+/// デバイスの接続状況の変化
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceEvent {
+    /// デバイスが接続された
+    Added(DeviceInfo),
+    /// デバイスが取り外された。値は接続されていた時のデバイス情報
+    Removed(DeviceInfo),
+}
+
+type DeviceEventHandler = Box<dyn Fn(DeviceEvent) + Send + Sync + 'static>;
+
+/// 前回のデバイス一覧と比較して変化をイベントにする
+///
+/// デバイスはポート名で区別するので、同じデバイスIDのデバイスが複数あってもそれぞれ通知されます。
+/// 同じデバイスIDでポート名が変わった場合は、取り外しと接続の両方を返します。
+fn diff(known: &mut HashMap<String, DeviceInfo>, current: Vec<DeviceInfo>) -> Vec<DeviceEvent> {
+    let mut events = Vec::new();
+
+    let current: HashMap<String, DeviceInfo> = current
+        .into_iter()
+        .map(|device| (device.port_name.clone(), device))
+        .collect();
+
+    known.retain(|port_name, device| {
+        if current.get(port_name) == Some(device) {
+            true
+        } else {
+            events.push(DeviceEvent::Removed(device.clone()));
+            false
+        }
+    });
+
+    for (port_name, device) in current {
+        if let Entry::Vacant(entry) = known.entry(port_name) {
+            events.push(DeviceEvent::Added(device.clone()));
+            entry.insert(device);
+        }
+    }
+
+    events
+}
+
+/// デバイスの接続・取り外しを監視する前に設定をおこないます。
+pub struct DeviceWatcherBuilder {
+    /// デバイス一覧を取得する間隔
+    interval: Duration,
+    /// 監視対象のデバイス
+    filter: DeviceFilter,
+
+    handler: Vec<DeviceEventHandler>,
+}
+
+impl Default for DeviceWatcherBuilder {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            filter: DeviceFilter::default(),
+            handler: Vec::new(),
+        }
+    }
+}
+
+impl DeviceWatcherBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// デバイス一覧を取得する間隔
+    ///
+    /// `udev` 機能が有効な場合は、udevの通知を受けた時にも取得します。
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// 監視対象のデバイス
+    pub fn filter(mut self, filter: DeviceFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// デバイスの接続状況が変化したときに実行するハンドラー
+    pub fn handler(mut self, handler: DeviceEventHandler) -> Self {
+        self.handler.push(handler);
+        self
+    }
+
+    pub fn build(self) -> DeviceWatcher {
+        DeviceWatcher {
+            interval: self.interval,
+            filter: Arc::new(self.filter),
+            handler: Arc::new(Mutex::new(self.handler)),
+            task: None,
+        }
+    }
+}
+
+/// シリアルポートデバイスの接続・取り外しを監視する
+///
+/// 監視を開始した時点で接続済みのデバイスも [`DeviceEvent::Added`] として通知されます。
+/// [`DeviceWatcher`] をドロップすると監視を終了します。
+///
+/// # Example
+///
+/// ```no_run
+/// use ardeck::device::{filter::DeviceFilter, watcher::DeviceWatcherBuilder};
+///
+/// let mut watcher = DeviceWatcherBuilder::new()
+///     .filter(DeviceFilter::arduino_compatible())
+///     .handler(Box::new(|event| println!("{:?}", event)))
+///     .build();
+///
+/// watcher.start();
+/// ```
+pub struct DeviceWatcher {
+    interval: Duration,
+    filter: Arc<DeviceFilter>,
+    handler: Arc<Mutex<Vec<DeviceEventHandler>>>,
+    task: Option<smol::Task<()>>,
+}
+
+impl DeviceWatcher {
+    pub async fn add_handler(&mut self, handler: DeviceEventHandler) {
+        self.handler.lock().await.push(handler);
+    }
+
+    pub fn start(&mut self) {
+        let interval = self.interval;
+        let filter = self.filter.clone();
+        let handler = self.handler.clone();
+
+        #[cfg(all(target_os = "linux", feature = "udev"))]
+        let mut notify = Some(udev_monitor());
+        #[cfg(not(all(target_os = "linux", feature = "udev")))]
+        let mut notify: Option<smol::channel::Receiver<()>> = None;
+
+        self.task = Some(smol::spawn(async move {
+            let mut known = HashMap::new();
+
+            loop {
+                // ポートの列挙はブロックするので、専用のスレッドでおこなう
+                let current = filter.apply(smol::unblock(available_list).await);
+                for event in diff(&mut known, current) {
+                    log::debug!("{:?}", event);
+
+                    for handler in handler.lock().await.iter() {
+                        handler(event.clone());
+                    }
+                }
+
+                poll::wait(&mut notify, interval).await;
+            }
+        }));
+    }
+
+    /// 監視を終了する
+    pub fn stop(&mut self) {
+        self.task = None;
+    }
+}
+
+/// udevでttyサブシステムの変化を監視し、変化があれば通知する
+///
+/// 監視を開始できなかった場合はチャンネルが閉じられます。
+#[cfg(all(target_os = "linux", feature = "udev"))]
+fn udev_monitor() -> smol::channel::Receiver<()> {
+    let (tx, rx) = smol::channel::bounded(1);
+
+    // udevのソケットはスレッド間で移動できないので、専用のスレッドで作成して待機する
+    std::thread::spawn(move || {
+        let socket = match udev::MonitorBuilder::new()
+            .and_then(|builder| builder.match_subsystem("tty"))
+            .and_then(|builder| builder.listen())
+            .and_then(smol::Async::new)
+        {
+            Ok(socket) => socket,
+            Err(e) => {
+                log::warn!("Failed start udev monitor, fallback to polling: {}", e);
+                return;
+            }
+        };
+
+        smol::block_on(async {
+            while socket.readable().await.is_ok() {
+                if socket.get_ref().iter().count() == 0 {
+                    continue;
+                }
+                if let Err(smol::channel::TrySendError::Closed(_)) = tx.try_send(()) {
+                    break;
+                }
+            }
+        })
+    });
+
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::test_device::device;
+
+    #[test]
+    fn diff_devices() {
+        let mut known = HashMap::new();
+        let a = device("/dev/ttyACM0", "A");
+        let b = device("/dev/ttyACM1", "B");
+
+        assert_eq!(
+            diff(&mut known, vec![a.clone()]),
+            vec![DeviceEvent::Added(a.clone())]
+        );
+        assert_eq!(diff(&mut known, vec![a.clone()]), vec![]);
+        assert_eq!(
+            diff(&mut known, vec![b.clone()]),
+            vec![
+                DeviceEvent::Removed(a.clone()),
+                DeviceEvent::Added(b.clone())
+            ]
+        );
+
+        let moved = device("/dev/ttyACM2", "B");
+        assert_eq!(
+            diff(&mut known, vec![moved.clone()]),
+            vec![DeviceEvent::Removed(b.clone()), DeviceEvent::Added(moved)]
+        );
+    }
+
+    #[test]
+    fn diff_same_device_id() {
+        let mut known = HashMap::new();
+        let a = device("/dev/ttyACM0", "A");
+        // シリアル番号を持たない同じ型番のデバイス
+        let clone = DeviceInfo {
+            port_name: "/dev/ttyACM1".into(),
+            ..a.clone()
+        };
+
+        let mut events = diff(&mut known, vec![a.clone(), clone.clone()]);
+        events.sort_by_key(|event| format!("{:?}", event));
+        assert_eq!(
+            events,
+            vec![
+                DeviceEvent::Added(a.clone()),
+                DeviceEvent::Added(clone.clone())
+            ]
+        );
+
+        assert_eq!(
+            diff(&mut known, vec![a.clone()]),
+            vec![DeviceEvent::Removed(clone)]
+        );
+        assert_eq!(known.len(), 1);
+    }
+}
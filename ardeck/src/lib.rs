@@ -6,3 +6,9 @@ pub mod config;
 
 #[cfg(any(test, feature = "store"))]
 pub mod store;
+
+#[cfg(any(test, feature = "action"))]
+pub mod action;
+
+#[cfg(any(test, feature = "device", feature = "store"))]
+mod poll;
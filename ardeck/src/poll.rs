@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+use smol::channel::Receiver;
+
+/// 通知を受けるか、次の確認の時間になるまで待つ
+///
+/// 通知のチャンネルが閉じられたら `notify` を `None` にし、以降はポーリングのみで待ちます。
+pub(crate) async fn wait(notify: &mut Option<Receiver<()>>, interval: Duration) {
+    match notify {
+        Some(rx) => {
+            let closed = smol::future::or(async { rx.recv().await.is_err() }, async {
+                smol::Timer::after(interval).await;
+                false
+            })
+            .await;
+
+            if closed {
+                // 通知が使えなくなったらポーリングのみで監視する
+                *notify = None;
+            }
+        }
+        None => {
+            smol::Timer::after(interval).await;
+        }
+    }
+}
@@ -1,13 +1,46 @@
-use std::{fs::create_dir_all, io::Write, path::PathBuf, sync::OnceLock};
+pub mod app_dirs;
+pub mod format;
+pub mod handle;
+pub mod layered;
+pub mod lenient;
+pub mod version;
+pub mod watch;
+
+#[cfg(test)]
+mod test_config;
+#[cfg(test)]
+mod test_dir;
+
+use std::{
+    ffi::OsString,
+    fs::{self, File, create_dir_all},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 
 use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
 
 use crate::config::ConfigFile;
 
-static STORE_PATH: OnceLock<PathBuf> = OnceLock::new();
+pub use app_dirs::{AppDirs, DirKind};
+pub use format::{Format, FormatError};
+pub use handle::ConfigHandle;
+pub use layered::{Layer, Layered, LayeredLoader};
+pub use lenient::FieldWarning;
+pub use watch::{ConfigWatcher, ConfigWatcherBuilder};
+
+static STORE: OnceLock<Store> = OnceLock::new();
 
-pub fn get_store_path() -> PathBuf {
-    STORE_PATH.get().unwrap().to_path_buf()
+/// グローバルな保存先のディレクトリを取得する
+///
+/// [`StoreBuilder::init`] で初期化されていなければエラーを返します。
+pub fn get_store_path() -> Result<PathBuf, Error> {
+    Ok(Store::global()?.path().to_path_buf())
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -16,68 +49,761 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("Serde error")]
     Serde(#[from] serde_json::Error),
+    #[error("Format error")]
+    Format(#[source] FormatError),
+    #[error("Global store is not initialized")]
+    NotInitialized,
+    #[error("Global store is already initialized")]
+    AlreadyInitialized,
+    /// ホームディレクトリが分からず、既定の保存先を決められない
+    #[error("Failed resolve {0:?} directory")]
+    NoBaseDirectory(DirKind),
+    /// 環境変数や上書きした値が項目の型に合わない
+    #[error("Invalid {layer} value for `{field}`: {message}")]
+    InvalidOverride {
+        layer: Layer,
+        field: String,
+        message: String,
+    },
+    /// ファイルのバージョンが [`ConfigFile::version`] より新しい
+    #[error("Unsupported version: found {found}, supported up to {supported}")]
+    UnsupportedVersion { found: u32, supported: u32 },
+}
+
+impl From<FormatError> for Error {
+    fn from(e: FormatError) -> Self {
+        match e {
+            FormatError::Json(e) => Self::Serde(e),
+            e => Self::Format(e),
+        }
+    }
 }
 
+/// 保存先を作成する前に設定をおこないます。
+///
+/// 保存先のディレクトリを指定しない場合は、[`StoreBuilder::kind`] の種類のプラットフォーム標準のディレクトリに
+/// [`StoreBuilder::app_name`] のディレクトリを作成します。 ex: `$XDG_CONFIG_HOME/ardeck`
 #[derive(Debug, Default)]
 pub struct StoreBuilder {
     /// 設定ファイルの保存先ディレクトリ
-    path: PathBuf,
+    path: Option<PathBuf>,
+    /// 既定の保存先に使うアプリ名
+    app_name: Option<String>,
+    /// 既定の保存先の種類
+    kind: DirKind,
+    /// 種類ごとの標準のディレクトリの代わりに使うディレクトリ
+    base_dir: Option<PathBuf>,
+    /// 保存時に残すバックアップの数
+    backups: usize,
 }
 
 impl StoreBuilder {
+    /// 設定ファイルの保存先ディレクトリ。存在しなければ作成されます
     pub fn path(mut self, path: PathBuf) -> Self {
-        assert!(path.is_dir(), "{} is not directory.", path.display());
-        self.path = path;
+        self.path = Some(path);
+        self
+    }
+
+    /// 既定の保存先に使うアプリ名。指定しない場合は [`app_dirs::DEFAULT_APP_NAME`]
+    pub fn app_name(mut self, app_name: impl Into<String>) -> Self {
+        self.app_name = Some(app_name.into());
         self
     }
 
-    pub fn init(self) {
-        create_dir_all(&self.path).expect("Failed create directry when store initialize.");
-        STORE_PATH.set(self.path).unwrap();
+    /// 既定の保存先の種類。指定しない場合は [`DirKind::Config`]
+    pub fn kind(mut self, kind: DirKind) -> Self {
+        self.kind = kind;
+        self
     }
+
+    /// 既定の保存先で、種類ごとの標準のディレクトリの代わりに使うディレクトリ
+    ///
+    /// このディレクトリに [`StoreBuilder::app_name`] のディレクトリを作成します。ポータブルな配置などに使えます。
+    pub fn base_dir(mut self, base_dir: PathBuf) -> Self {
+        self.base_dir = Some(base_dir);
+        self
+    }
+
+    /// 保存時に残すバックアップの数。0の時はバックアップを残さない
+    ///
+    /// 詳細は [`Store::with_backups`] を参照してください。
+    pub fn backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// 保存先を作成する
+    pub fn build(self) -> Result<Store, Error> {
+        let app_name = self
+            .app_name
+            .as_deref()
+            .unwrap_or(app_dirs::DEFAULT_APP_NAME);
+        let path = match (self.path, self.base_dir) {
+            (Some(path), _) => path,
+            (None, Some(base_dir)) => base_dir.join(app_name),
+            (None, None) => self.kind.resolve(app_name)?,
+        };
+
+        Ok(Store::new(path)?.with_backups(self.backups))
+    }
+
+    /// 保存先を作成し、グローバルな保存先として登録する
+    ///
+    /// 既に登録されている場合は [`Error::AlreadyInitialized`] を返します。
+    pub fn init(self) -> Result<(), Error> {
+        self.build()?.set_global()
+    }
+}
+
+/// 設定ファイルの保存先
+///
+/// 複数作成できるので、プロファイルごとやテストごとに別のディレクトリを使えます。
+///
+/// # Example
+///
+/// ```no_run
+/// use ardeck::store::Store;
+///
+/// let store = Store::new("./profile-a").unwrap();
+/// let another = Store::new("./profile-b").unwrap();
+/// assert_ne!(store, another);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Store {
+    path: PathBuf,
+    backups: usize,
+}
+
+impl Store {
+    /// ディレクトリを保存先にする。存在しなければ作成します
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        create_dir_all(&path)?;
+
+        Ok(Self { path, backups: 0 })
+    }
+
+    /// 保存時に残すバックアップの数を指定する。0の時はバックアップを残さない
+    ///
+    /// 保存の直前のファイルが `<ファイル名>.bak` に、それより古いものが `<ファイル名>.bak.1`, `<ファイル名>.bak.2`... に残ります。
+    /// 読み込み時に設定ファイルが壊れていた場合は、新しいバックアップから順に読み込みを試みます。
+    pub fn with_backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// 保存先のディレクトリ
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 設定ファイルまでのフルパスを取得する
+    ///
+    /// JSON以外の形式では、拡張子が [`StoreTrait::format`] の形式に合わせて置き換えられます。
+    /// JSONでは [`ConfigFile::name`] をそのままファイル名に使います。
+    pub fn file_path<T: StoreTrait>(&self) -> PathBuf {
+        let path = self.path.join(T::name());
+        match T::format() {
+            Format::Json => path,
+            #[allow(unreachable_patterns)]
+            format => path.with_extension(format.extension()),
+        }
+    }
+
+    /// 設定を共有するハンドルを取得する
+    ///
+    /// 同じ設定ファイルのハンドルが既にあれば、それと同じ値を共有します。
+    /// ファイルがなければ既定値で作成されます。
+    pub fn handle<T: StoreTrait + 'static>(&self) -> Result<ConfigHandle<T>, Error> {
+        ConfigHandle::open(self)
+    }
+
+    /// [`StoreBuilder::init`] または [`Store::set_global`] で登録された保存先を取得する
+    pub fn global() -> Result<&'static Store, Error> {
+        STORE.get().ok_or(Error::NotInitialized)
+    }
+
+    /// グローバルな保存先として登録する
+    pub fn set_global(self) -> Result<(), Error> {
+        STORE.set(self).map_err(|_| Error::AlreadyInitialized)
+    }
+
+    /// 新しいものから順にバックアップのパスを返す
+    fn backup_paths(&self, file_path: &Path) -> Vec<PathBuf> {
+        (0..self.backups)
+            .map(|i| {
+                let mut name = OsString::from(file_path.as_os_str());
+                name.push(".bak");
+                if i > 0 {
+                    name.push(format!(".{}", i));
+                }
+                PathBuf::from(name)
+            })
+            .collect()
+    }
+
+    /// 一時ファイルへ書き込んでから置き換えることで、書き込み途中のファイルが残らないようにする
+    ///
+    /// 一時ファイルの名前はプロセスと書き込みごとに変わるので、同時に保存しても衝突しません。
+    fn write_atomic(&self, file_path: &Path, contents: &[u8]) -> io::Result<()> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let mut tmp_name = OsString::from(file_path.as_os_str());
+        tmp_name.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let tmp_path = PathBuf::from(tmp_name);
+
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        drop(file);
+
+        if file_path.exists() {
+            self.rotate_backups(file_path)?;
+        }
+
+        fs::rename(&tmp_path, file_path)?;
+        watch::record_write(file_path, contents);
+
+        // リネームをディスクに反映させる
+        #[cfg(unix)]
+        File::open(&self.path)?.sync_all()?;
+
+        Ok(())
+    }
+
+    /// バックアップを1つずつ古い方へずらし、現在のファイルを最新のバックアップとしてコピーする
+    fn rotate_backups(&self, file_path: &Path) -> io::Result<()> {
+        let backups = self.backup_paths(file_path);
+        let Some(latest) = backups.first() else {
+            return Ok(());
+        };
+
+        for pair in backups.windows(2).rev() {
+            if pair[0].exists() {
+                fs::rename(&pair[0], &pair[1])?;
+            }
+        }
+
+        fs::copy(file_path, latest)?;
+        Ok(())
+    }
+}
+
+fn read_file<T: DeserializeOwned>(path: &Path, format: Format) -> Result<T, Error> {
+    let bytes = fs::read(path)?;
+
+    Ok(format.from_slice(&bytes)?)
+}
+
+/// 設定ファイルを読み込み、必要なら現在のバージョンへ移行する。移行した場合は `true` を返す
+fn read_config<T: StoreTrait>(path: &Path) -> Result<(T, bool), Error> {
+    parse_config(&fs::read(path)?)
+}
+
+/// 設定ファイルを値として読み込み、必要なら現在のバージョンへ移行したデータを返す
+fn read_data<T: StoreTrait>(path: &Path) -> Result<Value, Error> {
+    let value = read_file::<Value>(path, T::format())?;
+    if T::version() == 0 {
+        // バージョンを保存しない設定では、ファイル全体がデータ
+        return Ok(value);
+    }
+
+    let (found, data) = version::split(value);
+    if found > T::version() {
+        return Err(Error::UnsupportedVersion {
+            found,
+            supported: T::version(),
+        });
+    }
+
+    Ok(version::migrate::<T>(found, data))
+}
+
+/// 設定ファイルの内容をパースし、必要なら現在のバージョンへ移行する。移行した場合は `true` を返す
+fn parse_config<T: StoreTrait>(bytes: &[u8]) -> Result<(T, bool), Error> {
+    if T::version() == 0 {
+        return Ok((T::format().from_slice(bytes)?, false));
+    }
+
+    let (found, data) = version::split(T::format().from_slice::<Value>(bytes)?);
+    if found > T::version() {
+        return Err(Error::UnsupportedVersion {
+            found,
+            supported: T::version(),
+        });
+    }
+
+    let data = version::migrate::<T>(found, data);
+    Ok((serde_json::from_value(data)?, found < T::version()))
 }
 
 pub trait StoreTrait: Serialize + DeserializeOwned + ConfigFile + Clone + Send + Sync {
+    /// 設定ファイルの形式。指定しない場合はJSON
+    ///
+    /// `#[derive(Store)]` では `#[store(format = "toml")]` のように指定できます。
+    fn format() -> Format {
+        Format::Json
+    }
+
     /// 設定ファイルまでのフルパスを取得する
-    fn path() -> PathBuf {
-        STORE_PATH.get().unwrap().join(Self::name())
+    fn path(store: &Store) -> PathBuf {
+        store.file_path::<Self>()
     }
 
     /// 設定を読み込む
     ///
+    /// ファイルのバージョンが古い場合は [`ConfigFile::migrate`] で移行し、移行した結果を保存します。
+    ///
     /// # Example
     ///
-    /// ```
-    /// let mut my_config = MyConfig::load().unwrap_or_default();
+    /// ```no_run
+    /// # use ardeck::{config::ConfigFile, store::{Store, StoreTrait}};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Clone, Default, Deserialize, Serialize)]
+    /// # struct MyConfig { age: u32 }
+    /// # impl ConfigFile for MyConfig { fn name() -> &'static str { "my_config.json" } }
+    /// # impl StoreTrait for MyConfig {}
+    /// let store = Store::new("./config").unwrap();
+    ///
+    /// let mut my_config = MyConfig::load(&store).unwrap_or_default();
     /// my_config.age += 1;
-    /// my_config.save().unwrap();
+    /// my_config.save(&store).unwrap();
+    /// ```
+    fn load(store: &Store) -> Result<Self, Error> {
+        let path = Self::path(store);
+
+        let (config, migrated) = match read_config::<Self>(&path) {
+            Err(e @ (Error::Serde(_) | Error::Format(_))) => {
+                // 壊れている場合はバックアップから復元する
+                store
+                    .backup_paths(&path)
+                    .iter()
+                    .find_map(|backup| {
+                        let recovered = read_config::<Self>(backup).ok()?;
+                        log::warn!(
+                            "Failed parse {}, recovered from {}: {}",
+                            path.display(),
+                            backup.display(),
+                            e
+                        );
+                        Some(recovered)
+                    })
+                    .ok_or(e)?
+            }
+            result => result?,
+        };
+
+        // 移行した結果を保存し、次回からは移行せずに読み込めるようにする
+        if migrated {
+            log::info!("Migrated {} to version {}", path.display(), Self::version());
+            return config.save(store);
+        }
+
+        Ok(config)
+    }
+
+    /// 読み込めない項目を既定値にして設定を読み込む
+    ///
+    /// ファイルの各項目を [`Default::default`] の値に重ね、読み込めなかった項目は既定値のまま警告として返します。
+    /// ユーザーが手で編集したファイルの一部が不正でも、正しい項目は失われません。
+    /// 配列は丸ごと置き換えるので、要素の1つが不正なら配列全体が既定値になります。
+    /// ファイル自体が壊れていて項目に分けられない場合はエラーを返します。
+    ///
+    /// ファイルは書き換えないので、警告を確認してから必要に応じて [`StoreTrait::save`] してください。
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use ardeck::{config::ConfigFile, store::{Store, StoreTrait}};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Clone, Default, Deserialize, Serialize)]
+    /// # struct MyConfig { age: u32 }
+    /// # impl ConfigFile for MyConfig { fn name() -> &'static str { "my_config.json" } }
+    /// # impl StoreTrait for MyConfig {}
+    /// let store = Store::new("./config").unwrap();
+    ///
+    /// let (my_config, warnings) = MyConfig::load_lenient(&store).unwrap();
+    /// for warning in warnings {
+    ///     eprintln!("Ignored {}", warning);
+    /// }
     /// ```
-    fn load() -> Result<Self, Error> {
-        let file = std::fs::File::open(Self::path())?;
-        let reader = std::io::BufReader::new(file);
+    fn load_lenient(store: &Store) -> Result<(Self, Vec<FieldWarning>), Error> {
+        let path = Self::path(store);
+
+        let data = read_data::<Self>(&path)?;
+
+        let default = serde_json::to_value(Self::default())?;
+        let (value, warnings) = lenient::merge::<Self>(default, data);
 
-        Ok(serde_json::from_reader::<
-            std::io::BufReader<std::fs::File>,
-            Self,
-        >(reader)?)
+        for warning in &warnings {
+            log::warn!("Ignored invalid field in {}: {}", path.display(), warning);
+        }
+
+        Ok((serde_json::from_value(value)?, warnings))
     }
 
-    /// 設定を保存する
+    /// [`StoreTrait::load`] をブロッキング処理用のスレッドで実行する
+    ///
+    /// ファイルの読み込みで実行中のタスクを止めないので、ハンドラーやタスクの中から呼べます。
+    /// 特定の非同期ランタイムには依存しないので、smol以外のランタイムでも使えます。
     ///
     /// # Example
     ///
+    /// ```no_run
+    /// # use ardeck::{config::ConfigFile, store::{Store, StoreTrait}};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Clone, Default, Deserialize, Serialize)]
+    /// # struct MyConfig { age: u32 }
+    /// # impl ConfigFile for MyConfig { fn name() -> &'static str { "my_config.json" } }
+    /// # impl StoreTrait for MyConfig {}
+    /// let store = Store::new("./config").unwrap();
+    ///
+    /// smol::block_on(async {
+    ///     let mut my_config = MyConfig::load_async(&store).await.unwrap_or_default();
+    ///     my_config.age += 1;
+    ///     my_config.save_async(&store).await.unwrap();
+    /// });
     /// ```
-    /// let mut my_config = MyConfig::load().unwrap_or_default();
+    fn load_async(store: &Store) -> impl Future<Output = Result<Self, Error>> + Send + 'static
+    where
+        Self: 'static,
+    {
+        let store = store.clone();
+        smol::unblock(move || Self::load(&store))
+    }
+
+    /// [`StoreTrait::save`] をブロッキング処理用のスレッドで実行する
+    ///
+    /// 詳細は [`StoreTrait::load_async`] を参照してください。
+    fn save_async(self, store: &Store) -> impl Future<Output = Result<Self, Error>> + Send + 'static
+    where
+        Self: 'static,
+    {
+        let store = store.clone();
+        smol::unblock(move || self.save(&store))
+    }
+
+    /// 設定ファイルの変更を監視する
+    ///
+    /// ファイルが変更されるたびに読み込んだ値を受け取れます。[`StoreTrait::save`] による変更は通知されません。
+    /// 監視の間隔などを指定する場合は [`ConfigWatcherBuilder`] を使用します。
+    fn watch(store: &Store) -> ConfigWatcher<Self>
+    where
+        Self: 'static,
+    {
+        ConfigWatcherBuilder::new(store.clone()).build()
+    }
+
+    /// 保存されたファイルに環境変数などを重ねて読み込む [`LayeredLoader`] を作成する
+    ///
+    /// ファイルを書き換えずに設定を上書きしたい場合に使用します。
+    fn layered(store: &Store) -> LayeredLoader<Self> {
+        LayeredLoader::new().store(store.clone())
+    }
+
+    /// 設定を保存する
+    ///
+    /// 一時ファイルに書き込んでから置き換えるので、保存中にクラッシュしても元の設定ファイルは壊れません。
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use ardeck::{config::ConfigFile, store::{Store, StoreTrait}};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Clone, Default, Deserialize, Serialize)]
+    /// # struct MyConfig { age: u32 }
+    /// # impl ConfigFile for MyConfig { fn name() -> &'static str { "my_config.json" } }
+    /// # impl StoreTrait for MyConfig {}
+    /// let store = Store::global().unwrap();
+    ///
+    /// let mut my_config = MyConfig::load(store).unwrap_or_default();
     /// my_config.age += 1;
-    /// my_config.save().unwrap();
+    /// my_config.save(store).unwrap();
     /// ```
-    fn save(self) -> Result<Self, Error> {
-        let file = std::fs::File::create(Self::path())?;
-        let mut writer = std::io::BufWriter::new(file);
-
-        let file_str = serde_json::to_string_pretty(&self)?;
-        writer.write_all(file_str.as_bytes())?;
+    fn save(self, store: &Store) -> Result<Self, Error> {
+        let bytes = if Self::version() == 0 {
+            Self::format().to_vec(&self)?
+        } else {
+            let mut data = serde_json::to_value(&self)?;
+            if !Self::format().supports_null() {
+                version::remove_nulls(&mut data);
+            }
+            let data = version::wrap(Self::version(), data);
+            Self::format().to_vec(&data)?
+        };
+        store.write_atomic(&Self::path(store), &bytes)?;
 
         Ok(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::{test_config::Counter, test_dir::TestDir, *};
+
+    /// `count` を `total` に改名したバージョン
+    #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+    struct CounterV1 {
+        total: u32,
+    }
+
+    impl ConfigFile for CounterV1 {
+        fn name() -> &'static str {
+            "counter.json"
+        }
+
+        fn version() -> u32 {
+            1
+        }
+
+        fn migrate(from: u32, mut value: Value) -> Value {
+            if from == 0
+                && let Some(map) = value.as_object_mut()
+                && let Some(count) = map.remove("count")
+            {
+                map.insert("total".into(), count);
+            }
+            value
+        }
+    }
+
+    impl StoreTrait for CounterV1 {}
+
+    #[test]
+    fn backup_and_recover() {
+        let dir = TestDir::new("store_test");
+        let store = StoreBuilder::default()
+            .path(dir.path().to_path_buf())
+            .backups(2)
+            .build()
+            .unwrap();
+
+        for count in 1..=4 {
+            Counter { count }.save(&store).unwrap();
+        }
+
+        let path = Counter::path(&store);
+        let backups = store.backup_paths(&path);
+        assert_eq!(
+            read_file::<Counter>(&backups[0], Format::Json)
+                .unwrap()
+                .count,
+            3
+        );
+        assert_eq!(
+            read_file::<Counter>(&backups[1], Format::Json)
+                .unwrap()
+                .count,
+            2
+        );
+        assert!(no_temp_files(dir.path()));
+
+        // 書き込み途中で壊れたファイル
+        fs::write(&path, "{\"cou").unwrap();
+        assert_eq!(Counter::load(&store).unwrap().count, 3);
+    }
+
+    #[test]
+    fn concurrent_save() {
+        let dir = TestDir::new("store_concurrent");
+        let store = Store::new(dir.path()).unwrap();
+
+        // 同じファイルへ同時に保存しても一時ファイルが衝突しない
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    for count in 0..20 {
+                        Counter { count }.save(&store).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(Counter::load(&store).unwrap().count, 19);
+        assert!(no_temp_files(dir.path()));
+    }
+
+    #[test]
+    fn file_path() {
+        #[derive(Clone, Default, Deserialize, Serialize)]
+        struct Plain;
+
+        impl ConfigFile for Plain {
+            fn name() -> &'static str {
+                "foo.conf"
+            }
+        }
+
+        impl StoreTrait for Plain {}
+
+        let store = Store::new(std::env::temp_dir()).unwrap();
+
+        // JSONではファイル名を変えない
+        assert!(store.file_path::<Counter>().ends_with("counter.json"));
+        assert!(store.file_path::<Plain>().ends_with("foo.conf"));
+
+        #[cfg(feature = "toml")]
+        {
+            #[derive(Clone, Default, Deserialize, Serialize)]
+            struct Settings;
+
+            impl ConfigFile for Settings {
+                fn name() -> &'static str {
+                    "settings.json"
+                }
+            }
+
+            impl StoreTrait for Settings {
+                fn format() -> Format {
+                    Format::Toml
+                }
+            }
+
+            assert!(store.file_path::<Settings>().ends_with("settings.toml"));
+        }
+    }
+
+    fn no_temp_files(dir: &Path) -> bool {
+        fs::read_dir(dir).unwrap().all(|entry| {
+            entry
+                .unwrap()
+                .path()
+                .extension()
+                .is_none_or(|ext| ext != "tmp")
+        })
+    }
+
+    #[test]
+    fn load_lenient() {
+        #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+        struct Deck {
+            name: String,
+            retries: u16,
+            pins: Vec<u8>,
+        }
+
+        impl ConfigFile for Deck {
+            fn name() -> &'static str {
+                "deck.json"
+            }
+        }
+
+        impl StoreTrait for Deck {}
+
+        let dir = TestDir::new("store_lenient");
+        let store = Store::new(dir.path()).unwrap();
+
+        let contents = r#"{ "name": "left", "retries": -1, "pins": [1, 300] }"#;
+        fs::write(Deck::path(&store), contents).unwrap();
+
+        let (deck, warnings) = Deck::load_lenient(&store).unwrap();
+        assert_eq!(
+            deck,
+            Deck {
+                name: "left".into(),
+                ..Default::default()
+            }
+        );
+        let mut fields: Vec<&str> = warnings.iter().map(|w| w.field.as_str()).collect();
+        fields.sort();
+        assert_eq!(fields, ["pins", "retries"]);
+
+        // ファイルは書き換えない
+        assert_eq!(fs::read_to_string(Deck::path(&store)).unwrap(), contents);
+
+        // 項目に分けられないほど壊れたファイル
+        fs::write(Deck::path(&store), r#"{ "na"#).unwrap();
+        assert!(Deck::load_lenient(&store).is_err());
+    }
+
+    #[test]
+    fn migrate() {
+        let dir = TestDir::new("store_migrate");
+        let store = Store::new(dir.path()).unwrap();
+
+        Counter { count: 5 }.save(&store).unwrap();
+        assert_eq!(CounterV1::load(&store).unwrap(), CounterV1 { total: 5 });
+
+        // 移行した結果が保存されている
+        let saved: Value = read_file(&CounterV1::path(&store), Format::Json).unwrap();
+        assert_eq!(
+            saved,
+            serde_json::json!({ "$version": 1, "$data": { "total": 5 } })
+        );
+        assert_eq!(CounterV1::load(&store).unwrap().total, 5);
+
+        // 新しいバージョンのファイルは読み込まない
+        fs::write(
+            CounterV1::path(&store),
+            r#"{ "$version": 2, "$data": { "total": 5 } }"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            CounterV1::load(&store),
+            Err(Error::UnsupportedVersion {
+                found: 2,
+                supported: 1
+            })
+        ));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn versioned_toml() {
+        #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+        struct Profile {
+            name: String,
+            icon: Option<String>,
+        }
+
+        impl ConfigFile for Profile {
+            fn name() -> &'static str {
+                "profile.toml"
+            }
+
+            fn version() -> u32 {
+                1
+            }
+        }
+
+        impl StoreTrait for Profile {
+            fn format() -> Format {
+                Format::Toml
+            }
+        }
+
+        let dir = TestDir::new("store_toml");
+        let store = Store::new(dir.path()).unwrap();
+
+        // `None` の項目はnullにせず省略する
+        for icon in [None, Some("deck.png".to_string())] {
+            let profile = Profile {
+                name: "main".into(),
+                icon,
+            };
+            profile.clone().save(&store).unwrap();
+            assert_eq!(Profile::load(&store).unwrap(), profile);
+        }
+    }
+
+    #[test]
+    fn load_and_save_async() {
+        let dir = TestDir::new("store_async");
+        let store = Store::new(dir.path()).unwrap();
+
+        smol::block_on(async {
+            Counter { count: 7 }.save_async(&store).await.unwrap();
+            assert_eq!(Counter::load_async(&store).await.unwrap().count, 7);
+        });
+    }
+}
@@ -0,0 +1,144 @@
+use std::path::{Path, PathBuf};
+
+use crate::store::{Error, Store};
+
+/// アプリ名が指定されていない場合のアプリ名
+pub const DEFAULT_APP_NAME: &str = "ardeck";
+
+/// 保存するファイルの種類
+///
+/// 種類ごとにプラットフォームの標準のディレクトリを使います。Linuxでは XDG Base Directory に従います。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DirKind {
+    /// ユーザーが編集する設定 ex: `$XDG_CONFIG_HOME/<app>`
+    #[default]
+    Config,
+    /// アプリが生成して保持するデータ ex: `$XDG_DATA_HOME/<app>`
+    Data,
+    /// 消えても作り直せるキャッシュ ex: `$XDG_CACHE_HOME/<app>`
+    Cache,
+}
+
+impl DirKind {
+    /// アプリ名を含まない、種類ごとの基準のディレクトリ
+    fn base(&self) -> Option<PathBuf> {
+        match self {
+            Self::Config => dirs::config_dir(),
+            Self::Data => dirs::data_dir(),
+            Self::Cache => dirs::cache_dir(),
+        }
+    }
+
+    /// アプリのディレクトリ。ホームディレクトリが分からない場合はエラーを返す
+    pub fn resolve(&self, app_name: &str) -> Result<PathBuf, Error> {
+        self.base()
+            .map(|base| base.join(app_name))
+            .ok_or(Error::NoBaseDirectory(*self))
+    }
+}
+
+/// アプリの設定・データ・キャッシュのディレクトリ
+///
+/// # Example
+///
+/// ```no_run
+/// use ardeck::store::app_dirs::{AppDirs, DirKind};
+///
+/// let dirs = AppDirs::new("my-deck").unwrap();
+/// // ex: ~/.config/my-deck
+/// let config = dirs.store(DirKind::Config).unwrap();
+/// // ex: ~/.cache/my-deck
+/// let cache = dirs.store(DirKind::Cache).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppDirs {
+    config: PathBuf,
+    data: PathBuf,
+    cache: PathBuf,
+}
+
+impl AppDirs {
+    /// ディレクトリはまだ作成されません
+    pub fn new(app_name: &str) -> Result<Self, Error> {
+        Ok(Self {
+            config: DirKind::Config.resolve(app_name)?,
+            data: DirKind::Data.resolve(app_name)?,
+            cache: DirKind::Cache.resolve(app_name)?,
+        })
+    }
+
+    pub fn dir(&self, kind: DirKind) -> &Path {
+        match kind {
+            DirKind::Config => &self.config,
+            DirKind::Data => &self.data,
+            DirKind::Cache => &self.cache,
+        }
+    }
+
+    pub fn config(&self) -> &Path {
+        &self.config
+    }
+
+    pub fn data(&self) -> &Path {
+        &self.data
+    }
+
+    pub fn cache(&self) -> &Path {
+        &self.cache
+    }
+
+    /// 種類のディレクトリを保存先にする。存在しなければ作成します
+    pub fn store(&self, kind: DirKind) -> Result<Store, Error> {
+        Store::new(self.dir(kind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{StoreBuilder, test_dir::TestDir};
+
+    #[test]
+    fn resolve() {
+        let Ok(config) = DirKind::Config.resolve("my-deck") else {
+            // ホームディレクトリがない環境では既定の保存先を決められない
+            assert!(matches!(
+                StoreBuilder::default().build(),
+                Err(Error::NoBaseDirectory(DirKind::Config))
+            ));
+            return;
+        };
+
+        assert_eq!(config, dirs::config_dir().unwrap().join("my-deck"));
+        assert_eq!(
+            DirKind::Cache.resolve("my-deck").unwrap(),
+            dirs::cache_dir().unwrap().join("my-deck")
+        );
+
+        let app_dirs = AppDirs::new("my-deck").unwrap();
+        assert_eq!(app_dirs.dir(DirKind::Config), config);
+        assert_eq!(app_dirs.data(), dirs::data_dir().unwrap().join("my-deck"));
+        assert_ne!(app_dirs.config(), app_dirs.cache());
+    }
+
+    #[test]
+    fn builder_default_location() {
+        let dir = TestDir::new("store_app_dirs");
+
+        let store = StoreBuilder::default()
+            .base_dir(dir.path().to_path_buf())
+            .app_name("my-deck")
+            .kind(DirKind::Cache)
+            .build()
+            .unwrap();
+        // 存在しないディレクトリは作成される
+        assert_eq!(store.path(), dir.path().join("my-deck"));
+        assert!(store.path().is_dir());
+
+        let store = StoreBuilder::default()
+            .base_dir(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        assert_eq!(store.path(), dir.path().join(DEFAULT_APP_NAME));
+    }
+}
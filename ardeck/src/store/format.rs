@@ -0,0 +1,159 @@
+use serde::{Serialize, de::DeserializeOwned};
+
+/// 設定ファイルの形式
+///
+/// JSON以外の形式はそれぞれの機能を有効にすると使えます。
+///
+/// | 形式 | 拡張子 | 機能 |
+/// | --- | --- | --- |
+/// | [`Format::Json`] | `json` | |
+/// | `Format::Toml` | `toml` | `toml` |
+/// | `Format::Yaml` | `yaml` | `yaml` |
+/// | `Format::Ron` | `ron` | `ron` |
+/// | `Format::Cbor` | `cbor` | `cbor` |
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Format {
+    #[default]
+    Json,
+    #[cfg(feature = "toml")]
+    Toml,
+    #[cfg(feature = "yaml")]
+    Yaml,
+    #[cfg(feature = "ron")]
+    Ron,
+    /// バイナリ形式。人が編集することのない大きなデータに向いています
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum FormatError {
+    #[error("Json error")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid utf-8")]
+    Utf8(#[from] std::str::Utf8Error),
+    #[cfg(feature = "toml")]
+    #[error("Toml serialize error")]
+    TomlSerialize(#[from] toml::ser::Error),
+    #[cfg(feature = "toml")]
+    #[error("Toml deserialize error")]
+    TomlDeserialize(#[from] toml::de::Error),
+    #[cfg(feature = "yaml")]
+    #[error("Yaml error")]
+    Yaml(#[from] serde_yaml_ng::Error),
+    #[cfg(feature = "ron")]
+    #[error("Ron serialize error")]
+    RonSerialize(#[from] ron::Error),
+    #[cfg(feature = "ron")]
+    #[error("Ron deserialize error")]
+    RonDeserialize(#[from] ron::error::SpannedError),
+    #[cfg(feature = "cbor")]
+    #[error("Cbor serialize error")]
+    CborSerialize(#[from] ciborium::ser::Error<std::io::Error>),
+    #[cfg(feature = "cbor")]
+    #[error("Cbor deserialize error")]
+    CborDeserialize(#[from] ciborium::de::Error<std::io::Error>),
+}
+
+impl Format {
+    /// ファイルの拡張子
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            #[cfg(feature = "toml")]
+            Self::Toml => "toml",
+            #[cfg(feature = "yaml")]
+            Self::Yaml => "yaml",
+            #[cfg(feature = "ron")]
+            Self::Ron => "ron",
+            #[cfg(feature = "cbor")]
+            Self::Cbor => "cbor",
+        }
+    }
+
+    /// nullを表せる形式か。TOMLにはnullがありません
+    pub(crate) fn supports_null(&self) -> bool {
+        #[cfg(feature = "toml")]
+        if *self == Self::Toml {
+            return false;
+        }
+
+        true
+    }
+
+    /// 値をこの形式のバイト列にする。テキスト形式は人が読みやすいように整形されます
+    pub fn to_vec<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, FormatError> {
+        Ok(match self {
+            Self::Json => serde_json::to_vec_pretty(value)?,
+            #[cfg(feature = "toml")]
+            Self::Toml => toml::to_string_pretty(value)?.into_bytes(),
+            #[cfg(feature = "yaml")]
+            Self::Yaml => serde_yaml_ng::to_string(value)?.into_bytes(),
+            #[cfg(feature = "ron")]
+            Self::Ron => {
+                ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?.into_bytes()
+            }
+            #[cfg(feature = "cbor")]
+            Self::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)?;
+                bytes
+            }
+        })
+    }
+
+    /// この形式のバイト列から値を読み込む
+    pub fn from_slice<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, FormatError> {
+        Ok(match self {
+            Self::Json => serde_json::from_slice(bytes)?,
+            #[cfg(feature = "toml")]
+            Self::Toml => toml::from_str(std::str::from_utf8(bytes)?)?,
+            #[cfg(feature = "yaml")]
+            Self::Yaml => serde_yaml_ng::from_slice(bytes)?,
+            #[cfg(feature = "ron")]
+            Self::Ron => ron::de::from_bytes(bytes)?,
+            #[cfg(feature = "cbor")]
+            Self::Cbor => ciborium::from_reader(bytes)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Sample {
+        name: String,
+        pins: Vec<u8>,
+    }
+
+    #[test]
+    fn round_trip() {
+        let sample = Sample {
+            name: "deck".into(),
+            pins: vec![1, 2, 3],
+        };
+
+        let formats = [
+            Format::Json,
+            #[cfg(feature = "toml")]
+            Format::Toml,
+            #[cfg(feature = "yaml")]
+            Format::Yaml,
+            #[cfg(feature = "ron")]
+            Format::Ron,
+            #[cfg(feature = "cbor")]
+            Format::Cbor,
+        ];
+
+        for format in formats {
+            let bytes = format.to_vec(&sample).unwrap();
+            assert_eq!(format.from_slice::<Sample>(&bytes).unwrap(), sample);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    any::{Any, TypeId},
+    collections::BTreeMap,
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError, RwLock, Weak},
+};
+
+use smol::channel::{Receiver, Sender};
+
+use crate::store::{Error, Store, StoreTrait};
+
+type AnyInner = dyn Any + Send + Sync;
+
+/// 設定の型とファイルごとに共有されているハンドル
+///
+/// 同じファイルを別の型で開いても、それぞれの型のハンドルが残るように型も含めて区別します。
+static HANDLES: Mutex<BTreeMap<(TypeId, PathBuf), Weak<AnyInner>>> = Mutex::new(BTreeMap::new());
+
+struct Inner<T> {
+    store: Store,
+    value: RwLock<Arc<T>>,
+    /// 書き込みを1つずつおこなうためのロック
+    write: Mutex<()>,
+    subscribers: Mutex<Vec<Sender<Arc<T>>>>,
+}
+
+/// 複数のスレッドで共有できる設定のハンドル
+///
+/// 読み込んだ値をキャッシュするので、[`ConfigHandle::get`] はファイルを読みません。
+/// 同じ設定ファイルのハンドルは [`Store::handle`] で何度取得しても同じ値を共有します。
+///
+/// # Example
+///
+/// ```no_run
+/// # use ardeck::{config::ConfigFile, store::{Store, StoreTrait}};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Clone, Default, Deserialize, Serialize)]
+/// # struct MyConfig { age: u32 }
+/// # impl ConfigFile for MyConfig { fn name() -> &'static str { "my_config.json" } }
+/// # impl StoreTrait for MyConfig {}
+/// let store = Store::new("./config").unwrap();
+/// let handle = store.handle::<MyConfig>().unwrap();
+///
+/// let changes = handle.subscribe();
+/// handle.update(|my_config| my_config.age += 1).unwrap();
+///
+/// assert_eq!(changes.try_recv().unwrap().age, handle.get().age);
+/// ```
+pub struct ConfigHandle<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for ConfigHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: StoreTrait + 'static> ConfigHandle<T> {
+    pub(super) fn open(store: &Store) -> Result<Self, Error> {
+        let key = (TypeId::of::<T>(), T::path(store));
+        let mut handles = HANDLES.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(inner) = handles
+            .get(&key)
+            .and_then(Weak::upgrade)
+            .and_then(|inner| inner.downcast::<Inner<T>>().ok())
+        {
+            return Ok(Self { inner });
+        }
+
+        let value = match T::load(store) {
+            Ok(value) => value,
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => T::default(),
+            Err(e) => return Err(e),
+        };
+
+        let inner = Arc::new(Inner {
+            store: store.clone(),
+            value: RwLock::new(Arc::new(value)),
+            write: Mutex::new(()),
+            subscribers: Mutex::new(Vec::new()),
+        });
+
+        handles.retain(|_, handle| handle.strong_count() > 0);
+        handles.insert(key, Arc::downgrade(&(inner.clone() as Arc<AnyInner>)));
+
+        Ok(Self { inner })
+    }
+
+    /// 現在の値
+    pub fn get(&self) -> Arc<T> {
+        self.inner
+            .value
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// 値を変更して保存する
+    ///
+    /// 同時に呼ばれた場合は1つずつ順に処理されます。保存に失敗した場合、値は変更されません。
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, Error> {
+        let _write = self
+            .inner
+            .write
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let mut value = T::clone(&self.get());
+        let result = f(&mut value);
+        self.replace(value.save(&self.inner.store)?);
+
+        Ok(result)
+    }
+
+    /// ファイルから読み込み直す
+    ///
+    /// 外部でファイルが変更された場合に使用します。変更は購読者に通知されます。
+    pub fn reload(&self) -> Result<(), Error> {
+        let _write = self
+            .inner
+            .write
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        self.replace(T::load(&self.inner.store)?);
+        Ok(())
+    }
+
+    /// 値が変更されるたびに新しい値を受け取る
+    pub fn subscribe(&self) -> Receiver<Arc<T>> {
+        let (tx, rx) = smol::channel::unbounded();
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(tx);
+
+        rx
+    }
+
+    fn replace(&self, value: T) {
+        let value = Arc::new(value);
+        *self
+            .inner
+            .value
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = value.clone();
+
+        // 受信側がドロップされた購読は取り除く
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|tx| tx.try_send(value.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{
+        config::ConfigFile,
+        store::{test_config::Counter, test_dir::TestDir},
+    };
+
+    #[test]
+    fn shared() {
+        let dir = TestDir::new("store_handle");
+        let store = Store::new(dir.path()).unwrap();
+
+        let handle = store.handle::<Counter>().unwrap();
+        let changes = handle.subscribe();
+        assert_eq!(handle.get().count, 0);
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let handle = handle.clone();
+                std::thread::spawn(move || handle.update(|c| c.count += 1).unwrap())
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // 別に取得したハンドルも同じ値を共有する
+        assert_eq!(store.handle::<Counter>().unwrap().get().count, 8);
+        assert_eq!(Counter::load(&store).unwrap().count, 8);
+        assert_eq!(changes.len(), 8);
+    }
+
+    #[test]
+    fn same_path_different_type() {
+        /// 同じファイルを別の型で読む設定
+        #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+        struct Other {
+            count: u32,
+        }
+
+        impl ConfigFile for Other {
+            fn name() -> &'static str {
+                "counter.json"
+            }
+        }
+
+        impl StoreTrait for Other {}
+
+        let dir = TestDir::new("store_handle_type");
+        let store = Store::new(dir.path()).unwrap();
+
+        let counter = store.handle::<Counter>().unwrap();
+        let other = store.handle::<Other>().unwrap();
+
+        // 別の型で開いても、それぞれの型のハンドルが共有されたまま
+        assert!(Arc::ptr_eq(
+            &store.handle::<Counter>().unwrap().inner,
+            &counter.inner
+        ));
+        assert!(Arc::ptr_eq(
+            &store.handle::<Other>().unwrap().inner,
+            &other.inner
+        ));
+    }
+}
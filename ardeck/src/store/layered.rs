@@ -0,0 +1,443 @@
+use std::{collections::BTreeMap, fmt, io, marker::PhantomData};
+
+use serde_json::{Map, Value};
+
+use crate::store::{Error, Store, StoreTrait, read_data};
+
+/// 環境変数の接頭辞の先頭
+pub const ENV_PREFIX: &str = "ARDECK";
+
+/// 環境変数でネストした項目を区切る文字列
+pub const ENV_SEPARATOR: &str = "__";
+
+/// 値を与えた層。後の層ほど優先されます
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    /// [`Default::default`] の値
+    Default,
+    /// [`Store`] に保存されたファイル
+    File,
+    /// 環境変数
+    Env,
+    /// [`LayeredLoader::set`] などで与えた値
+    Override,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Default => "default",
+            Self::File => "file",
+            Self::Env => "env",
+            Self::Override => "override",
+        };
+        f.write_str(name)
+    }
+}
+
+/// 層を重ねて読み込んだ設定
+#[derive(Debug, Clone)]
+pub struct Layered<T> {
+    pub config: T,
+    /// 項目ごとの値を与えた層。ネストした項目は `.` で区切る ex: `devices.left.alias`
+    pub sources: BTreeMap<String, Layer>,
+}
+
+impl<T> Layered<T> {
+    /// 項目の値を与えた層
+    ///
+    /// オブジェクトを指定した場合は、その中の項目のうち最も優先される層を返します。
+    pub fn source(&self, field: &str) -> Option<Layer> {
+        if let Some(layer) = self.sources.get(field) {
+            return Some(*layer);
+        }
+
+        let nested = format!("{}.", field);
+        self.sources
+            .iter()
+            .filter(|(key, _)| key.starts_with(&nested))
+            .map(|(_, layer)| *layer)
+            .max()
+    }
+}
+
+/// 既定値、保存されたファイル、環境変数、プログラムから与えた値の順に重ねて設定を読み込む
+///
+/// 環境変数は `ARDECK_<型名>__<項目>` の形式で、ネストした項目は `__` で区切ります。
+/// ex: `MyConfig` の `devices.left.alias` は `ARDECK_MYCONFIG__DEVICES__LEFT__ALIAS`
+///
+/// 環境変数やコマンドライン引数の値は、JSONとして読める場合はJSONとして扱います。
+/// 既定値やファイルの値が文字列の項目では、そのまま文字列として扱います。
+///
+/// 読み込んだ値は保存されないので、ファイルを書き換えずに設定を上書きできます。
+///
+/// # Example
+///
+/// ```no_run
+/// # use ardeck::{config::ConfigFile, store::{Store, StoreTrait, layered::Layer}};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Clone, Default, Deserialize, Serialize)]
+/// # struct MyConfig { name: String, age: u32 }
+/// # impl ConfigFile for MyConfig { fn name() -> &'static str { "my_config.json" } }
+/// # impl StoreTrait for MyConfig {}
+/// let store = Store::new("./config").unwrap();
+///
+/// // ex: ARDECK_MYCONFIG__AGE=42 で起動した場合
+/// let layered = MyConfig::layered(&store)
+///     .arg("name=John Doe")
+///     .unwrap()
+///     .load()
+///     .unwrap();
+///
+/// assert_eq!(layered.source("age"), Some(Layer::Env));
+/// assert_eq!(layered.source("name"), Some(Layer::Override));
+/// ```
+pub struct LayeredLoader<T> {
+    store: Option<Store>,
+    prefix: String,
+    /// 指定しない場合はプロセスの環境変数
+    vars: Option<Vec<(String, String)>>,
+    overrides: Vec<(String, Override)>,
+
+    _config: PhantomData<fn() -> T>,
+}
+
+enum Override {
+    Value(Value),
+    /// 項目の型に合わせて解釈する文字列
+    Raw(String),
+}
+
+impl<T: StoreTrait> Default for LayeredLoader<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: StoreTrait> LayeredLoader<T> {
+    /// ファイルの層を含まない状態で作成する
+    pub fn new() -> Self {
+        Self {
+            store: None,
+            prefix: default_prefix::<T>(),
+            vars: None,
+            overrides: Vec::new(),
+            _config: PhantomData,
+        }
+    }
+
+    /// ファイルを読み込む保存先。ファイルが存在しない場合はファイルの層を飛ばします
+    pub fn store(mut self, store: Store) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// 環境変数の接頭辞。指定しない場合は `ARDECK_<型名>`
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// プロセスの環境変数の代わりに使う変数
+    pub fn vars<K, V>(mut self, vars: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.vars = Some(
+            vars.into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        );
+        self
+    }
+
+    /// 項目の値を上書きする。ネストした項目は `.` で区切る
+    pub fn set(mut self, field: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.overrides
+            .push((field.into(), Override::Value(value.into())));
+        self
+    }
+
+    /// `field=value` の形式のコマンドライン引数で項目の値を上書きする
+    pub fn arg(mut self, arg: &str) -> Result<Self, Error> {
+        let (field, value) = arg
+            .split_once('=')
+            .filter(|(field, _)| !field.is_empty())
+            .ok_or_else(|| Error::InvalidOverride {
+                layer: Layer::Override,
+                field: arg.to_string(),
+                message: "expected `field=value`".to_string(),
+            })?;
+
+        self.overrides
+            .push((field.to_string(), Override::Raw(value.to_string())));
+        Ok(self)
+    }
+
+    /// 層を重ねて読み込む
+    ///
+    /// 環境変数や上書きした値が項目の型に合わない場合は、どの層のどの項目かを含むエラーを返します。
+    pub fn load(self) -> Result<Layered<T>, Error> {
+        let mut root = serde_json::to_value(T::default())?;
+        let mut sources = BTreeMap::new();
+        record(&root, &mut Vec::new(), Layer::Default, &mut sources);
+
+        if let Some(store) = &self.store {
+            match read_data::<T>(&T::path(store)) {
+                Ok(file) => {
+                    record(&file, &mut Vec::new(), Layer::File, &mut sources);
+                    merge(&mut root, file);
+                    serde_json::from_value::<T>(root.clone())?;
+                }
+                Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let vars = self
+            .vars
+            .unwrap_or_else(|| std::env::vars().collect::<Vec<_>>());
+        let head = format!("{}{}", self.prefix, ENV_SEPARATOR);
+        for (key, value) in vars {
+            let Some(field) = key.strip_prefix(&head) else {
+                continue;
+            };
+            let path = resolve(&root, field.split(ENV_SEPARATOR));
+            let value = parse(pointer(&root, &path), value);
+
+            apply::<T>(&mut root, &path, value, Layer::Env, &mut sources)?;
+        }
+
+        for (field, value) in self.overrides {
+            let path = resolve(&root, field.split('.'));
+            let value = match value {
+                Override::Value(value) => value,
+                Override::Raw(raw) => parse(pointer(&root, &path), raw),
+            };
+
+            apply::<T>(&mut root, &path, value, Layer::Override, &mut sources)?;
+        }
+
+        Ok(Layered {
+            config: serde_json::from_value(root)?,
+            sources,
+        })
+    }
+}
+
+/// `ARDECK_` に型名を大文字にして続けた接頭辞
+fn default_prefix<T>() -> String {
+    let name = std::any::type_name::<T>();
+    let name = name.split('<').next().unwrap_or(name);
+    let name = name.rsplit("::").next().unwrap_or(name);
+
+    format!("{}_{}", ENV_PREFIX, name.to_uppercase())
+}
+
+/// 大文字小文字を区別せずに既存の項目名に合わせる。存在しない項目は小文字にする
+fn resolve<'a>(root: &Value, segments: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut path = Vec::new();
+
+    for segment in segments {
+        let key = pointer(root, &path)
+            .and_then(Value::as_object)
+            .and_then(|map| {
+                map.keys()
+                    .find(|key| key.eq_ignore_ascii_case(segment))
+                    .cloned()
+            })
+            .unwrap_or_else(|| segment.to_lowercase());
+        path.push(key);
+    }
+
+    path
+}
+
+/// 現在の値が文字列ならそのまま、それ以外はJSONとして読めればJSONとして解釈する
+fn parse(current: Option<&Value>, raw: String) -> Value {
+    if let Some(Value::String(_)) = current {
+        return Value::String(raw);
+    }
+
+    serde_json::from_str(&raw).unwrap_or(Value::String(raw))
+}
+
+fn apply<T: StoreTrait>(
+    root: &mut Value,
+    path: &[String],
+    value: Value,
+    layer: Layer,
+    sources: &mut BTreeMap<String, Layer>,
+) -> Result<(), Error> {
+    let field = path.join(".");
+    let current = pointer(root, path).cloned();
+
+    set(root, path, value.clone());
+    if let Err(e) = serde_json::from_value::<T>(root.clone()) {
+        if let Some(current) = current {
+            set(root, path, current);
+        }
+        return Err(Error::InvalidOverride {
+            layer,
+            field,
+            message: e.to_string(),
+        });
+    }
+
+    // 置き換えた項目の中の項目は、すべてこの層の値になる
+    let nested = format!("{}.", field);
+    sources.retain(|key, _| !key.starts_with(&nested));
+    record(&value, &mut path.to_vec(), layer, sources);
+
+    Ok(())
+}
+
+/// オブジェクトの項目を再帰的に重ねる
+fn merge(root: &mut Value, value: Value) {
+    match (root, value) {
+        (Value::Object(root), Value::Object(value)) => {
+            for (key, value) in value {
+                match root.get_mut(&key) {
+                    Some(current) => merge(current, value),
+                    None => {
+                        root.insert(key, value);
+                    }
+                }
+            }
+        }
+        (root, value) => *root = value,
+    }
+}
+
+/// 値の末端の項目ごとに層を記録する
+fn record(
+    value: &Value,
+    path: &mut Vec<String>,
+    layer: Layer,
+    sources: &mut BTreeMap<String, Layer>,
+) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                path.push(key.clone());
+                record(value, path, layer, sources);
+                path.pop();
+            }
+        }
+        _ => {
+            sources.insert(path.join("."), layer);
+        }
+    }
+}
+
+fn pointer<'a>(root: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(root, |value, key| value.get(key))
+}
+
+/// `path` の値を置き換える。途中のオブジェクトが存在しなければ作成する
+fn set(root: &mut Value, path: &[String], value: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        *root = value;
+        return;
+    };
+
+    let mut current = root;
+    for key in parents {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        current = current
+            .as_object_mut()
+            .map(|map| map.entry(key.clone()).or_insert(Value::Object(Map::new())))
+            .expect("value is object");
+    }
+
+    if !current.is_object() {
+        *current = Value::Object(Map::new());
+    }
+    if let Some(map) = current.as_object_mut() {
+        map.insert(last.clone(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{config::ConfigFile, store::test_dir::TestDir};
+
+    #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+    struct Device {
+        alias: String,
+        brightness: u8,
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+    struct MyConfig {
+        name: String,
+        retries: u16,
+        device: Device,
+    }
+
+    impl ConfigFile for MyConfig {
+        fn name() -> &'static str {
+            "my_config.json"
+        }
+    }
+
+    impl StoreTrait for MyConfig {}
+
+    #[test]
+    fn layers() {
+        let dir = TestDir::new("store_layered");
+        let store = Store::new(dir.path()).unwrap();
+        MyConfig {
+            name: "file".into(),
+            retries: 3,
+            device: Device {
+                alias: "left".into(),
+                brightness: 10,
+            },
+        }
+        .save(&store)
+        .unwrap();
+
+        let layered = MyConfig::layered(&store)
+            .vars([
+                ("ARDECK_MYCONFIG__RETRIES", "5"),
+                ("ARDECK_MYCONFIG__DEVICE__ALIAS", "42"),
+                ("OTHER__RETRIES", "7"),
+            ])
+            .arg("device.brightness=200")
+            .unwrap()
+            .load()
+            .unwrap();
+
+        assert_eq!(
+            layered.config,
+            MyConfig {
+                name: "file".into(),
+                retries: 5,
+                device: Device {
+                    alias: "42".into(),
+                    brightness: 200,
+                },
+            }
+        );
+        assert_eq!(layered.source("name"), Some(Layer::File));
+        assert_eq!(layered.source("retries"), Some(Layer::Env));
+        assert_eq!(layered.source("device.alias"), Some(Layer::Env));
+        assert_eq!(layered.source("device"), Some(Layer::Override));
+
+        let e = MyConfig::layered(&store)
+            .vars([("ARDECK_MYCONFIG__DEVICE__BRIGHTNESS", "300")])
+            .load()
+            .unwrap_err();
+        assert!(matches!(
+            e,
+            Error::InvalidOverride { layer: Layer::Env, ref field, .. } if field == "device.brightness"
+        ));
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// 緩い読み込みで使えなかった設定の項目
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldWarning {
+    /// 項目の位置。ネストした項目は `.` で区切る ex: `devices.left.alias`
+    pub field: String,
+    /// 使えなかった理由
+    pub message: String,
+}
+
+impl fmt::Display for FieldWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`: {}", self.field, self.message)
+    }
+}
+
+/// ファイルの値を既定値に重ね、`T` として読み込める項目だけを残す
+///
+/// オブジェクトの項目は再帰的に重ねるので、ネストした項目の1つが不正でも同じオブジェクトの他の項目は残ります。
+/// 配列は要素ごとには重ねず丸ごと置き換えるので、要素の1つが不正なら配列全体が既定値のままになります。
+///
+/// 項目をまとめて重ねてから一度だけ確かめ、読み込めない時だけ項目を半分ずつに分けて確かめ直します。
+/// 正しいファイルなら確かめるのは1回だけです。
+pub(super) fn merge<T: DeserializeOwned>(
+    default: Value,
+    file: Value,
+) -> (Value, Vec<FieldWarning>) {
+    let mut root = default;
+    let mut warnings = Vec::new();
+
+    match file {
+        Value::Object(file) if root.is_object() => {
+            let fields = file.into_iter().collect();
+            merge_fields::<T>(&mut root, &mut Vec::new(), fields, &mut warnings);
+        }
+        // 項目に分けられないので、全体で読み込めるか確かめる
+        file => match serde_json::from_value::<T>(file.clone()) {
+            Ok(_) => root = file,
+            Err(e) => warnings.push(FieldWarning {
+                field: String::new(),
+                message: e.to_string(),
+            }),
+        },
+    }
+
+    (root, warnings)
+}
+
+/// `path` のオブジェクトに `fields` を重ねる。読み込めなければ半分ずつに分けて重ね直す
+fn merge_fields<T: DeserializeOwned>(
+    root: &mut Value,
+    path: &mut Vec<String>,
+    mut fields: Vec<(String, Value)>,
+    warnings: &mut Vec<FieldWarning>,
+) {
+    if fields.is_empty() {
+        return;
+    }
+
+    let mut previous = Vec::with_capacity(fields.len());
+    for (key, value) in &fields {
+        path.push(key.clone());
+        previous.push(pointer(root, path).cloned());
+        overlay(root, path, value.clone());
+        path.pop();
+    }
+
+    let Err(e) = serde_json::from_value::<T>(root.clone()) else {
+        return;
+    };
+
+    // 重ねる前に戻す
+    for ((key, _), value) in fields.iter().zip(previous) {
+        path.push(key.clone());
+        set(root, path, value);
+        path.pop();
+    }
+
+    if fields.len() > 1 {
+        let rest = fields.split_off(fields.len() / 2);
+        merge_fields::<T>(root, path, fields, warnings);
+        merge_fields::<T>(root, path, rest, warnings);
+        return;
+    }
+
+    let Some((key, value)) = fields.pop() else {
+        return;
+    };
+    path.push(key);
+    match (pointer(root, path), value) {
+        (Some(Value::Object(_)), Value::Object(map)) => {
+            merge_fields::<T>(root, path, map.into_iter().collect(), warnings);
+        }
+        _ => {
+            // 読み込めない項目は既定値のまま
+            warnings.push(FieldWarning {
+                field: path.join("."),
+                message: e.to_string(),
+            });
+        }
+    }
+    path.pop();
+}
+
+/// `path` に値を重ねる。どちらもオブジェクトなら項目ごとに、それ以外は置き換える
+fn overlay(root: &mut Value, path: &mut Vec<String>, value: Value) {
+    match (pointer(root, path), value) {
+        (Some(Value::Object(_)), Value::Object(map)) => {
+            for (key, value) in map {
+                path.push(key);
+                overlay(root, path, value);
+                path.pop();
+            }
+        }
+        (_, value) => set(root, path, Some(value)),
+    }
+}
+
+fn pointer<'a>(root: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(root, |value, key| value.get(key))
+}
+
+/// `path` の値を置き換える。`None` の時は取り除く
+fn set(root: &mut Value, path: &[String], value: Option<Value>) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let Some(Value::Object(map)) = parents
+        .iter()
+        .try_fold(root, |value, key| value.get_mut(key))
+    else {
+        return;
+    };
+
+    match value {
+        Some(value) => {
+            map.insert(last.clone(), value);
+        }
+        None => {
+            map.remove(last);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+    struct Inner {
+        alias: String,
+        brightness: u8,
+    }
+
+    #[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+    struct Config {
+        name: String,
+        retries: u16,
+        inner: Inner,
+    }
+
+    #[test]
+    fn keep_valid_fields() {
+        let default = serde_json::to_value(Config::default()).unwrap();
+        let file = json!({
+            "name": "deck",
+            "retries": -1,
+            "inner": { "alias": "left", "brightness": 300 },
+        });
+
+        let (value, warnings) = merge::<Config>(default, file);
+        assert_eq!(
+            serde_json::from_value::<Config>(value).unwrap(),
+            Config {
+                name: "deck".into(),
+                retries: 0,
+                inner: Inner {
+                    alias: "left".into(),
+                    brightness: 0,
+                },
+            }
+        );
+
+        let mut fields: Vec<&str> = warnings.iter().map(|w| w.field.as_str()).collect();
+        fields.sort();
+        assert_eq!(fields, ["inner.brightness", "retries"]);
+    }
+}
@@ -0,0 +1,19 @@
+//! テスト用の設定
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::ConfigFile, store::StoreTrait};
+
+/// 数値を1つだけ持つ設定
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Counter {
+    pub count: u32,
+}
+
+impl ConfigFile for Counter {
+    fn name() -> &'static str {
+        "counter.json"
+    }
+}
+
+impl StoreTrait for Counter {}
@@ -0,0 +1,38 @@
+//! テスト用の一時ディレクトリ
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// ドロップした時に中身ごと削除される一時ディレクトリ
+///
+/// テストが途中でパニックしても削除されます。
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    /// `name` を含む空のディレクトリを作成する。同じ名前でも呼ぶたびに別のディレクトリになります
+    pub fn new(name: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "ardeck_{}_{}_{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
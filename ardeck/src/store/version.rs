@@ -0,0 +1,70 @@
+//! 設定ファイルのスキーマのバージョン
+//!
+//! [`ConfigFile::version`] が1以上の場合、設定ファイルは次のようにバージョンとデータを並べて保存されます。
+//!
+//! ```json
+//! {
+//!   "$version": 1,
+//!   "$data": { "years": 42 }
+//! }
+//! ```
+
+use serde_json::{Map, Value};
+
+use crate::config::ConfigFile;
+
+/// バージョンを保存するキー
+pub const VERSION_KEY: &str = "$version";
+
+/// データを保存するキー
+pub const DATA_KEY: &str = "$data";
+
+/// 読み込んだ値からバージョンとデータを取り出す。バージョンが保存されていなければ0とみなす
+pub(super) fn split(value: Value) -> (u32, Value) {
+    if let Value::Object(map) = &value
+        && map.len() == 2
+        && let Some(version) = map
+            .get(VERSION_KEY)
+            .and_then(Value::as_u64)
+            .and_then(|version| u32::try_from(version).ok())
+        && let Some(data) = map.get(DATA_KEY)
+    {
+        return (version, data.clone());
+    }
+
+    (0, value)
+}
+
+/// バージョンとデータをまとめる
+pub(super) fn wrap(version: u32, data: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(VERSION_KEY.to_string(), version.into());
+    map.insert(DATA_KEY.to_string(), data);
+
+    Value::Object(map)
+}
+
+/// オブジェクトの値がnullの項目を取り除く
+///
+/// nullを表せない形式で保存するために使います。
+/// 構造体を直接保存した時に `None` の項目が省略されるのと同じになります。
+pub(super) fn remove_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, value| !value.is_null());
+            map.values_mut().for_each(remove_nulls);
+        }
+        Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+/// バージョン `from` のデータを現在のバージョンまで順に移行する
+pub(super) fn migrate<T: ConfigFile>(from: u32, mut data: Value) -> Value {
+    for version in from..T::version() {
+        log::debug!("Migrate {} from version {}", T::name(), version);
+        data = T::migrate(version, data);
+    }
+
+    data
+}
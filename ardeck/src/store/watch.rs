@@ -0,0 +1,281 @@
+use std::{
+    collections::BTreeMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use smol::channel::Receiver;
+
+use crate::{
+    poll,
+    store::{Store, StoreTrait, parse_config},
+};
+
+/// このプロセスが最後に書き込んだ内容のハッシュ
+static WRITTEN: Mutex<BTreeMap<PathBuf, u64>> = Mutex::new(BTreeMap::new());
+
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// [`StoreTrait::save`] で書き込んだ内容を記録し、監視で無視できるようにする
+pub(super) fn record_write(path: &Path, bytes: &[u8]) {
+    if let Ok(mut written) = WRITTEN.lock() {
+        written.insert(path.to_path_buf(), hash(bytes));
+    }
+}
+
+fn is_own_write(path: &Path, hash: u64) -> bool {
+    WRITTEN
+        .lock()
+        .map(|written| written.get(path) == Some(&hash))
+        .unwrap_or(false)
+}
+
+/// 設定ファイルの監視を開始する前に設定をおこないます。
+pub struct ConfigWatcherBuilder<T> {
+    store: Store,
+    /// 変更を確認する間隔
+    interval: Duration,
+    /// 書き込みが落ち着いたとみなすまでの待機時間
+    debounce: Duration,
+
+    _config: PhantomData<fn() -> T>,
+}
+
+impl<T: StoreTrait + 'static> ConfigWatcherBuilder<T> {
+    pub fn new(store: Store) -> Self {
+        Self {
+            store,
+            interval: Duration::from_secs(1),
+            debounce: Duration::from_millis(200),
+            _config: PhantomData,
+        }
+    }
+
+    /// 変更を確認する間隔
+    ///
+    /// `notify` 機能が有効な場合は、ファイルシステムの通知を受けた時にも確認します。
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// 書き込みが落ち着いたとみなすまでの待機時間
+    ///
+    /// この時間だけ内容が変わらなければ読み込みます。書き込み途中のファイルを読まないようにするためのものです。
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// 監視を開始する
+    pub fn build(self) -> ConfigWatcher<T> {
+        let (tx, rx) = smol::channel::unbounded();
+        let path = T::path(&self.store);
+
+        #[cfg(feature = "notify")]
+        let notify = notify_watcher(self.store.path());
+        #[cfg(not(feature = "notify"))]
+        let notify: Option<((), Receiver<()>)> = None;
+
+        let task = smol::spawn(async move {
+            // 通知の監視はタスクが続く間保持する
+            let (_watcher, mut notify) = match notify {
+                Some((watcher, rx)) => (Some(watcher), Some(rx)),
+                None => (None, None),
+            };
+
+            // 監視を開始した時点の内容は通知しない
+            let mut last = read(&path).await.map(|bytes| hash(&bytes));
+
+            loop {
+                poll::wait(&mut notify, self.interval).await;
+
+                let Some(bytes) = settle(&path, &mut notify, self.debounce).await else {
+                    continue;
+                };
+                let current = hash(&bytes);
+                if last == Some(current) {
+                    continue;
+                }
+                if is_own_write(&path, current) {
+                    last = Some(current);
+                    continue;
+                }
+
+                match parse_config::<T>(&bytes) {
+                    Ok((config, _)) => {
+                        last = Some(current);
+                        log::info!("Reloaded {}", path.display());
+
+                        if tx.send(config).await.is_err() {
+                            break;
+                        }
+                    }
+                    // 次の変更で読み込み直す
+                    Err(e) => log::warn!("Failed reload {}: {}", path.display(), e),
+                }
+            }
+        });
+
+        ConfigWatcher { rx, _task: task }
+    }
+}
+
+/// 設定ファイルの変更を監視し、変更されるたびに読み込んだ値を送る
+///
+/// [`ConfigWatcher`] をドロップすると監視を終了します。
+///
+/// # Example
+///
+/// ```no_run
+/// # use ardeck::{config::ConfigFile, store::{Store, StoreTrait}};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Clone, Default, Deserialize, Serialize)]
+/// # struct MyConfig { age: u32 }
+/// # impl ConfigFile for MyConfig { fn name() -> &'static str { "my_config.json" } }
+/// # impl StoreTrait for MyConfig {}
+/// let store = Store::new("./config").unwrap();
+///
+/// smol::block_on(async {
+///     let watcher = MyConfig::watch(&store);
+///     while let Some(my_config) = watcher.recv().await {
+///         println!("age: {}", my_config.age);
+///     }
+/// });
+/// ```
+pub struct ConfigWatcher<T> {
+    rx: Receiver<T>,
+    _task: smol::Task<()>,
+}
+
+impl<T> ConfigWatcher<T> {
+    /// 次に変更されるまで待ち、読み込んだ値を返す
+    pub async fn recv(&self) -> Option<T> {
+        self.rx.recv().await.ok()
+    }
+
+    /// 読み込んだ値を受け取る [`Receiver`]。[`Stream`](smol::stream::Stream) として使えます
+    pub fn receiver(&self) -> Receiver<T> {
+        self.rx.clone()
+    }
+}
+
+async fn read(path: &Path) -> Option<Vec<u8>> {
+    let path = path.to_path_buf();
+    smol::unblock(move || std::fs::read(path).ok()).await
+}
+
+/// 内容が `debounce` の間変わらなくなるまで待ってから読み込む
+async fn settle(
+    path: &Path,
+    notify: &mut Option<Receiver<()>>,
+    debounce: Duration,
+) -> Option<Vec<u8>> {
+    let mut bytes = read(path).await?;
+
+    loop {
+        // 通知が続いている間は待ち続ける
+        loop {
+            let notified = match notify {
+                Some(rx) => {
+                    smol::future::or(async { rx.recv().await.is_ok() }, async {
+                        smol::Timer::after(debounce).await;
+                        false
+                    })
+                    .await
+                }
+                None => {
+                    smol::Timer::after(debounce).await;
+                    false
+                }
+            };
+
+            if !notified {
+                break;
+            }
+        }
+
+        let again = read(path).await?;
+        if again == bytes {
+            return Some(bytes);
+        }
+        bytes = again;
+    }
+}
+
+/// ディレクトリの変更をファイルシステムの通知で監視する
+///
+/// 保存時はファイルを置き換えるので、ファイルではなくディレクトリを監視します。
+#[cfg(feature = "notify")]
+fn notify_watcher(dir: &Path) -> Option<(notify::RecommendedWatcher, Receiver<()>)> {
+    use notify::Watcher;
+
+    let (tx, rx) = smol::channel::bounded(1);
+
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if event.is_ok() {
+            let _ = tx.try_send(());
+        }
+    })
+    .and_then(|mut watcher| {
+        watcher.watch(dir, notify::RecursiveMode::NonRecursive)?;
+        Ok(watcher)
+    });
+
+    match watcher {
+        Ok(watcher) => Some((watcher, rx)),
+        Err(e) => {
+            log::warn!("Failed start file watcher, fallback to polling: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{test_config::Counter, test_dir::TestDir};
+
+    /// 値が届くまで最大で `timeout` だけ待つ
+    async fn recv_timeout<T>(watcher: &ConfigWatcher<T>, timeout: Duration) -> Option<T> {
+        smol::future::or(watcher.recv(), async {
+            smol::Timer::after(timeout).await;
+            None
+        })
+        .await
+    }
+
+    #[test]
+    fn watch() {
+        let dir = TestDir::new("store_watch");
+        let store = Store::new(dir.path()).unwrap();
+        Counter { count: 1 }.save(&store).unwrap();
+
+        let watcher = ConfigWatcherBuilder::<Counter>::new(store.clone())
+            .interval(Duration::from_millis(20))
+            .debounce(Duration::from_millis(20))
+            .build();
+
+        smol::block_on(async {
+            // 自分で保存した変更は通知されない
+            Counter { count: 2 }.save(&store).unwrap();
+            assert_eq!(
+                recv_timeout(&watcher, Duration::from_millis(300)).await,
+                None
+            );
+
+            std::fs::write(Counter::path(&store), r#"{ "count": 3 }"#).unwrap();
+            assert_eq!(
+                recv_timeout(&watcher, Duration::from_secs(5)).await,
+                Some(Counter { count: 3 })
+            );
+        });
+    }
+}
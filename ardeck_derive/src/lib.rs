@@ -1,16 +1,66 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{DeriveInput, parse_macro_input};
+use quote::{format_ident, quote};
+use syn::{DeriveInput, LitStr, parse_macro_input};
 
-#[proc_macro_derive(Store)]
+/// `ardeck::store::StoreTrait` を実装する
+///
+/// `#[store(format = "toml")]` で設定ファイルの形式を指定できます。
+/// 指定できる形式は `json`, `toml`, `yaml`, `ron`, `cbor` です。
+#[proc_macro_derive(Store, attributes(store))]
 pub fn store_derive(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
 
+    match expand(&ast) {
+        Ok(quote) => TokenStream::from(quote),
+        Err(e) => TokenStream::from(e.to_compile_error()),
+    }
+}
+
+fn expand(ast: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
     let name = &ast.ident;
 
-    let quote = quote! {
-        impl ardeck::store::StoreTrait for #name {}
-    };
+    let mut format = None;
+    for attr in ast
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("store"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if !meta.path.is_ident("format") {
+                return Err(meta.error("unsupported store attribute"));
+            }
+
+            let value: LitStr = meta.value()?.parse()?;
+            let variant = match value.value().as_str() {
+                "json" => "Json",
+                "toml" => "Toml",
+                "yaml" => "Yaml",
+                "ron" => "Ron",
+                "cbor" => "Cbor",
+                other => {
+                    return Err(syn::Error::new(
+                        value.span(),
+                        format!("unknown format `{}`", other),
+                    ));
+                }
+            };
+            format = Some(format_ident!("{}", variant));
+
+            Ok(())
+        })?;
+    }
+
+    let format_fn = format.map(|variant| {
+        quote! {
+            fn format() -> ardeck::store::Format {
+                ardeck::store::Format::#variant
+            }
+        }
+    });
 
-    TokenStream::from(quote)
+    Ok(quote! {
+        impl ardeck::store::StoreTrait for #name {
+            #format_fn
+        }
+    })
 }
@@ -5,6 +5,10 @@ use ardeck::{
 use ardeck_derive::Store;
 use serde::{Deserialize, Serialize};
 
+mod common;
+
+use common::TestDir;
+
 #[derive(Debug, Serialize, Deserialize, Clone, Store)]
 struct MyConfig {
     name: String,
@@ -25,14 +29,53 @@ impl ConfigFile for MyConfig {
     }
 }
 
-#[test]
-fn derive() {
-    #[test]
-    fn store_builder() {
-        StoreBuilder::default().path("./".into()).init();
-
-        let mut my_config = MyConfig::load().unwrap_or_default();
-        my_config.age += 1;
-        my_config.save().unwrap();
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Store)]
+#[store(format = "toml")]
+struct TomlConfig {
+    name: String,
+}
+
+impl ConfigFile for TomlConfig {
+    fn name() -> &'static str {
+        "toml_config"
     }
 }
+
+#[test]
+fn store_builder() {
+    let dir = TestDir::new("derive_test");
+    let store = StoreBuilder::default()
+        .path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    let mut my_config = MyConfig::load(&store).unwrap_or_default();
+    my_config.age += 1;
+    my_config.save(&store).unwrap();
+
+    assert_eq!(MyConfig::load(&store).unwrap().age, 43);
+}
+
+#[test]
+fn store_format() {
+    let dir = TestDir::new("derive_format");
+    let store = StoreBuilder::default()
+        .path(dir.path().to_path_buf())
+        .build()
+        .unwrap();
+
+    let config = TomlConfig {
+        name: "deck".into(),
+    };
+    config.clone().save(&store).unwrap();
+
+    assert_eq!(
+        TomlConfig::path(&store),
+        dir.path().join("toml_config.toml")
+    );
+    assert_eq!(
+        std::fs::read_to_string(TomlConfig::path(&store)).unwrap(),
+        "name = \"deck\"\n"
+    );
+    assert_eq!(TomlConfig::load(&store).unwrap(), config);
+}